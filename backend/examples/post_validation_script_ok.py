@@ -4,7 +4,8 @@ import sys
 from time import sleep
 
 if __name__ == '__main__':
-    arg_json = sys.argv[1]
+    # the payload is passed on stdin (input_mode: stdin) or as the last argument (input_mode: argv)
+    arg_json = sys.argv[1] if len(sys.argv) > 1 else sys.stdin.read()
 
     json.loads(arg_json)
 
@@ -13,8 +14,9 @@ if __name__ == '__main__':
         print('Validation script waiting...')
         sleep(1)
 
-    # set TORII_OUTPUT to the output of the validation script
-    # TORII_OUTPUT is used by the backend to pass the output of the validation to the next step and to the frontend
-    os.environ['TORII_JSON_OUTPUT'] = '{"status": "OK", "message": "Post validation script OK"}'
+    # the JSON output is written to the file given by the backend in TORII_OUTPUT_FILE,
+    # it is stored with the task and available to the next tasks as {{ outputs.<task id>.<key> }}
+    with open(os.environ['TORII_OUTPUT_FILE'], 'w') as output_file:
+        json.dump({"status": "OK", "message": "Post validation script OK"}, output_file)
 
     print('OK')
@@ -3,7 +3,8 @@ import sys
 from time import sleep
 
 if __name__ == '__main__':
-    arg_json = sys.argv[1]
+    # the payload is passed on stdin (input_mode: stdin) or as the last argument (input_mode: argv)
+    arg_json = sys.argv[1] if len(sys.argv) > 1 else sys.stdin.read()
 
     j = json.loads(arg_json)
     # wait for 5 seconds
@@ -11,4 +12,10 @@ if __name__ == '__main__':
         print('Validation script waiting...')
         sleep(1)
 
+    # errors are reported with a JSON line on stderr, returned by the validate endpoint to highlight the fields
+    print(json.dumps({
+        'message': 'the environment can not be created',
+        'field_errors': {'name': ['an environment named %s already exists' % j.get('name')]},
+    }), file=sys.stderr)
+
     exit(1)
@@ -4,7 +4,8 @@ import sys
 from time import sleep
 
 if __name__ == '__main__':
-    arg_json = sys.argv[1]
+    # the payload is passed on stdin (input_mode: stdin) or as the last argument (input_mode: argv)
+    arg_json = sys.argv[1] if len(sys.argv) > 1 else sys.stdin.read()
 
     j = json.loads(arg_json)
 
@@ -13,4 +14,5 @@ if __name__ == '__main__':
         print('Validation script waiting...')
         sleep(1)
 
-    os.environ["TORII_JSON_OUTPUT"] = json.dumps({"status": "OK", "message": "Validation script OK"})
+    with open(os.environ["TORII_OUTPUT_FILE"], "w") as output_file:
+        json.dump({"status": "OK", "message": "Validation script OK"}, output_file)
@@ -0,0 +1,42 @@
+-- create status enum if it doesn't exist
+DO
+$$
+    BEGIN
+        IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'status') THEN
+            CREATE TYPE status AS ENUM (
+                'QUEUED',
+                'RUNNING',
+                'SUCCESS',
+                'FAILURE'
+                );
+        END IF;
+    END
+$$;
+
+-- create a new flat table to store action runs
+CREATE TABLE IF NOT EXISTS self_service_runs
+(
+    id            UUID PRIMARY KEY DEFAULT gen_random_uuid() NOT NULL,
+    created_at    TIMESTAMP        DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at    TIMESTAMP        DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    section_slug  VARCHAR(255)                               NOT NULL,
+    action_slug   VARCHAR(255)                               NOT NULL,
+    status        status                                     NOT NULL,
+    input_payload JSONB DEFAULT '{}'::jsonb                  NOT NULL,
+    tasks         JSONB DEFAULT '{}'::jsonb                  NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS self_service_runs_section_slug_idx ON self_service_runs (section_slug);
+CREATE INDEX IF NOT EXISTS self_service_runs_action_slug_idx ON self_service_runs (action_slug);
+
+-- create a table to store the output of the commands executed during a run
+CREATE TABLE IF NOT EXISTS self_service_run_logs
+(
+    id         BIGSERIAL PRIMARY KEY                                         NOT NULL,
+    run_id     UUID REFERENCES self_service_runs (id) ON DELETE CASCADE      NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP                           NOT NULL,
+    is_stderr  BOOLEAN   DEFAULT FALSE                                       NOT NULL,
+    message    TEXT                                                          NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS self_service_run_logs_run_id_idx ON self_service_run_logs (run_id);
@@ -0,0 +1,4 @@
+-- statuses added after the creation of the enum
+ALTER TYPE status ADD VALUE IF NOT EXISTS 'ROLLED_BACK';
+ALTER TYPE status ADD VALUE IF NOT EXISTS 'ROLLBACK_FAILED';
+ALTER TYPE status ADD VALUE IF NOT EXISTS 'TIMED_OUT';
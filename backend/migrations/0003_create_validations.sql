@@ -0,0 +1,13 @@
+-- create a table to store the results of the validate scripts executed asynchronously
+CREATE TABLE IF NOT EXISTS self_service_validations
+(
+    id            UUID PRIMARY KEY DEFAULT gen_random_uuid() NOT NULL,
+    created_at    TIMESTAMP        DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at    TIMESTAMP        DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    section_slug  VARCHAR(255)                               NOT NULL,
+    action_slug   VARCHAR(255)                               NOT NULL,
+    status        status                                     NOT NULL,
+    payload_hash  VARCHAR(64)                                NOT NULL,
+    input_payload JSONB DEFAULT '{}'::jsonb                  NOT NULL,
+    tasks         JSONB DEFAULT '[]'::jsonb                  NOT NULL
+);
@@ -0,0 +1,6 @@
+-- columns added to filter and paginate the runs
+ALTER TABLE self_service_runs ADD COLUMN IF NOT EXISTS dry_run BOOLEAN DEFAULT FALSE NOT NULL;
+ALTER TABLE self_service_runs ADD COLUMN IF NOT EXISTS triggered_by VARCHAR(255);
+
+CREATE INDEX IF NOT EXISTS self_service_runs_created_at_idx ON self_service_runs (created_at, id);
+CREATE INDEX IF NOT EXISTS self_service_runs_input_payload_idx ON self_service_runs USING GIN (input_payload jsonb_path_ops);
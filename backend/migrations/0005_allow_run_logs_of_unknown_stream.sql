@@ -0,0 +1,2 @@
+-- the stream of a line is unknown when stdout and stderr are mixed, e.g. the logs of a Kubernetes pod
+ALTER TABLE self_service_run_logs ALTER COLUMN is_stderr DROP NOT NULL;
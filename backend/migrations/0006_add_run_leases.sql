@@ -0,0 +1,5 @@
+-- the backend executing a run renews its lease, the other replicas only take the run over once the lease expired
+ALTER TABLE self_service_runs ADD COLUMN IF NOT EXISTS owner VARCHAR(255);
+ALTER TABLE self_service_runs ADD COLUMN IF NOT EXISTS lease_until TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS self_service_runs_unfinished_idx ON self_service_runs (lease_until) WHERE status IN ('QUEUED', 'RUNNING');
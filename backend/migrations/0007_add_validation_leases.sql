@@ -0,0 +1,5 @@
+-- the backend executing a validation renews its lease, the other replicas only fail the validation once the lease expired
+ALTER TABLE self_service_validations ADD COLUMN IF NOT EXISTS owner VARCHAR(255);
+ALTER TABLE self_service_validations ADD COLUMN IF NOT EXISTS lease_until TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS self_service_validations_unfinished_idx ON self_service_validations (lease_until) WHERE status IN ('QUEUED', 'RUNNING');
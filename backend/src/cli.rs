@@ -1,14 +1,67 @@
+use std::net::IpAddr;
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+
+use crate::yaml_config::DatabaseTlsMode;
 
 /// Torii is a simple, powerful and extensible open-source Internal Developer Portal, just pass `-h`
 #[derive(Parser, Debug)]
 #[clap(version, about, long_about = None)]
 #[clap(propagate_version = true)]
-pub struct CLI {
+#[clap(subcommand_negates_reqs = true)]
+pub struct Cli {
     /// Torii configuration file
-    #[clap(short, long, value_name = "configuration file")]
-    pub config: PathBuf,
+    #[clap(short, long, value_name = "configuration file", required = true)]
+    pub config: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub overrides: ConfigOverrides,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Server and database settings, they take precedence over the `server` and `database` blocks of the config file
+#[derive(Args, Debug, Default)]
+#[clap(next_help_heading = "Server and database")]
+pub struct ConfigOverrides {
+    /// Address the HTTP server listens on
+    #[clap(long, env = "BIND_ADDRESS")]
+    pub bind_address: Option<IpAddr>,
+    /// Port the HTTP server listens on
+    #[clap(long, env = "PORT")]
+    pub port: Option<u16>,
+    /// Postgres connection URL
+    #[clap(long, env = "DB_CONNECTION_URL", hide_env_values = true)]
+    pub db_connection_url: Option<String>,
+    /// Maximum number of connections of the database pool
+    #[clap(long, env = "DB_MAX_CONNECTIONS")]
+    pub db_max_connections: Option<u32>,
+    /// How long to wait for a connection of the database pool, in seconds
+    #[clap(long, env = "DB_ACQUIRE_TIMEOUT_SECONDS")]
+    pub db_acquire_timeout_seconds: Option<u64>,
+    /// TLS mode of the database connection, overrides the `sslmode` of the URL
+    #[clap(long, env = "DB_TLS_MODE")]
+    pub db_tls_mode: Option<DatabaseTlsMode>,
+    /// CA certificate used to verify the database server
+    #[clap(long, env = "DB_TLS_ROOT_CERT")]
+    pub db_tls_root_cert: Option<PathBuf>,
+    /// Bearer token of the admin endpoints (e.g. `POST /admin/config/reload`), they are disabled when not set
+    #[clap(long, env = "ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+}
 
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Apply the pending database migrations and exit
+    Migrate,
+    /// Check the configuration and report all its problems, exit with a non-zero code if there is any
+    ValidateConfig {
+        /// Torii configuration file or directory
+        #[clap(short, long, value_name = "configuration file")]
+        config: PathBuf,
+    },
+    /// Print the JSON Schema of the configuration files, for the completion and the validation in the editors
+    ConfigSchema,
 }
@@ -0,0 +1,515 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::yaml_config::YamlConfig;
+
+/// A config file as read from the disk
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub content: Vec<u8>,
+}
+
+/// Only the includes of a config file, to find the files to read before parsing them
+#[derive(Deserialize, Default)]
+struct ConfigIncludes {
+    #[serde(default)]
+    include: Vec<String>,
+}
+
+/// Read the config files: the given file, or the YAML files of the given directory, followed by the files they include.
+/// A file included several times is read once.
+pub fn read_config_files(path: &Path) -> Result<Vec<ConfigFile>, String> {
+    let mut files = vec![];
+    let mut visited = BTreeSet::new();
+
+    let roots = match path.is_dir() {
+        true => yaml_files_in_dir(path)?,
+        false => vec![path.to_path_buf()],
+    };
+
+    for root in roots {
+        read_config_file(&root, &mut files, &mut visited)?;
+    }
+
+    Ok(files)
+}
+
+fn read_config_file(path: &Path, files: &mut Vec<ConfigFile>, visited: &mut BTreeSet<PathBuf>) -> Result<(), String> {
+    let canonical_path = path.canonicalize()
+        .map_err(|err| format!("failed to open config file '{}': {}", path.display(), err))?;
+
+    if !visited.insert(canonical_path) {
+        return Ok(());
+    }
+
+    let content = std::fs::read(path)
+        .map_err(|err| format!("failed to open config file '{}': {}", path.display(), err))?;
+
+    // an invalid file is reported when it's parsed with the others
+    let includes = parse_config_file::<ConfigIncludes>(&content, &env_var).ok().flatten().unwrap_or_default();
+    let base_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    files.push(ConfigFile { path: path.to_path_buf(), content });
+
+    for pattern in includes.include {
+        for included_path in resolve_include(&base_dir, &pattern)? {
+            read_config_file(&included_path, files, visited)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Files matching an include, relative to the directory of the including file: a file, a directory (its YAML files),
+/// or a pattern with `*` and `?` in the file name (e.g. `sections/*.yaml`)
+fn resolve_include(base_dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = base_dir.join(pattern);
+
+    let file_name = path.file_name().and_then(|file_name| file_name.to_str()).unwrap_or_default();
+
+    if !file_name.contains(['*', '?']) {
+        return match path.is_dir() {
+            true => yaml_files_in_dir(&path),
+            false => Ok(vec![path]),
+        };
+    }
+
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    if dir.to_str().is_some_and(|dir| dir.contains(['*', '?'])) {
+        return Err(format!("invalid include '{}': only the file name can contain wildcards", pattern));
+    }
+
+    let mut paths = list_dir(&dir)?.into_iter()
+        .filter(|path| path.is_file())
+        .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| wildcard_match(file_name, name)))
+        .collect::<Vec<_>>();
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn yaml_files_in_dir(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut paths = list_dir(dir)?.into_iter()
+        .filter(|path| path.is_file())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "yaml" || extension == "yml"))
+        .collect::<Vec<_>>();
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    std::fs::read_dir(dir)
+        .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.path())).collect())
+        .map_err(|err| format!("failed to list config directory '{}': {}", dir.display(), err))
+}
+
+/// `*` matches any sequence of characters and `?` any single character
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // matches[j] is true when the pattern read so far matches the first j characters of the name
+    let mut matches = vec![false; name.len() + 1];
+    matches[0] = true;
+
+    for c in pattern {
+        let previous = matches.clone();
+
+        matches[0] = c == '*' && previous[0];
+
+        for j in 1..=name.len() {
+            matches[j] = match c {
+                '*' => previous[j] || matches[j - 1],
+                '?' => previous[j - 1],
+                c => previous[j - 1] && name[j - 1] == c,
+            };
+        }
+    }
+
+    matches[name.len()]
+}
+
+/// Replace the `${VAR}` and `${VAR:-default}` references in the string values of a parsed config file, the default
+/// is used when the variable is not set or empty. `$${VAR}` is kept as `${VAR}`. The commands are not interpolated,
+/// their arguments are passed as is (e.g. `sh -c 'echo ${HOME}'`). A value made of a single reference is read as a
+/// number or a boolean when it looks like one (e.g. `port: ${PORT}`), an interpolated value can't change the structure of the file.
+pub fn interpolate_env(value: &mut Value, env: &impl Fn(&str) -> Option<String>) -> Result<(), String> {
+    interpolate_value(value, "", env)
+}
+
+fn interpolate_value(value: &mut Value, path: &str, env: &impl Fn(&str) -> Option<String>) -> Result<(), String> {
+    match value {
+        Value::String(string) => {
+            let interpolated = interpolate_string(string, env).map_err(|err| format!("{}: {}", path, err))?;
+
+            *value = match is_single_reference(string) {
+                true => match serde_yaml::from_str::<Value>(&interpolated) {
+                    Ok(scalar @ (Value::Number(_) | Value::Bool(_))) => scalar,
+                    _ => Value::String(interpolated),
+                },
+                false => Value::String(interpolated),
+            };
+        }
+        Value::Sequence(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                interpolate_value(value, &format!("{}[{}]", path, index), env)?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                let key = key.as_str().map(|key| key.to_string()).unwrap_or_else(|| format!("{:?}", key));
+
+                if key == "command" && value.is_sequence() {
+                    continue;
+                }
+
+                let path = match path.is_empty() {
+                    true => key,
+                    false => format!("{}.{}", path, key),
+                };
+
+                interpolate_value(value, &path, env)?;
+            }
+        }
+        Value::Tagged(tagged) => interpolate_value(&mut tagged.value, path, env)?,
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+
+    Ok(())
+}
+
+fn is_single_reference(string: &str) -> bool {
+    string.starts_with("${") && string.find('}') == Some(string.len() - 1)
+}
+
+fn interpolate_string(string: &str, env: &impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut interpolated = String::with_capacity(string.len());
+    let mut rest = string;
+
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            interpolated.push_str(&rest[..start - 1]);
+            interpolated.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        interpolated.push_str(&rest[..start]);
+
+        let end = rest[start..].find('}')
+            .ok_or_else(|| "unterminated variable reference".to_string())?;
+
+        let reference = &rest[start + 2..start + end];
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit())
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("invalid variable name '{}'", name));
+        }
+
+        let value = match (env(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => default.to_string(),
+            (Some(value), _) => value,
+            (None, Some(default)) => default.to_string(),
+            (None, None) => return Err(format!(
+                "environment variable '{}' is not set, give it a default with ${{{}:-default}} or escape it with $${{{}}}",
+                name, name, name,
+            )),
+        };
+
+        interpolated.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+
+    interpolated.push_str(rest);
+
+    Ok(interpolated)
+}
+
+/// Parse a config file, after the interpolation of its values
+fn parse_config_file<T: DeserializeOwned>(content: &[u8], env: &impl Fn(&str) -> Option<String>) -> Result<Option<T>, String> {
+    let mut value = serde_yaml::from_slice::<Value>(content).map_err(|err| err.to_string())?;
+
+    interpolate_env(&mut value, env)?;
+
+    // read back from YAML rather than from the value, so that a plain scalar is still read as a string when a string is
+    // expected (e.g. `placeholder: 24`)
+    let interpolated = serde_yaml::to_string(&value).map_err(|err| err.to_string())?;
+
+    serde_yaml::from_str(&interpolated).map_err(|err| {
+        let message = err.to_string();
+
+        // the position is the one of the interpolated document, not of the file - the path of the invalid value is kept
+        match err.location() {
+            Some(location) => message.trim_end_matches(&format!(" at line {} column {}", location.line(), location.column())).to_string(),
+            None => message,
+        }
+    })
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Merge the sections of the config files, in the order of the files. The server, the database, the retention and the
+/// default input mode are defined once, the sections defined in several files are reported when the config is validated.
+pub fn merge_config_files(files: &[ConfigFile]) -> Result<YamlConfig, String> {
+    if files.is_empty() {
+        return Err("no config file found".to_string());
+    }
+
+    let mut merged = YamlConfig::default();
+    let mut defined_in = BTreeMap::new();
+
+    for file in files {
+        let source_file = file.path.display().to_string();
+
+        let mut config = parse_config_file::<YamlConfig>(&file.content, &env_var)
+            .map_err(|err| format!("failed to parse config file '{}': {}", source_file, err))?
+            .unwrap_or_default();
+
+        for section in config.self_service.sections.iter_mut() {
+            section.source_file = Some(source_file.clone());
+        }
+
+        merge_once("server", &mut merged.server, config.server, &source_file, &mut defined_in)?;
+        merge_once("database", &mut merged.database, config.database, &source_file, &mut defined_in)?;
+        merge_once("retention", &mut merged.self_service.retention, config.self_service.retention, &source_file, &mut defined_in)?;
+        merge_once("default_input_mode", &mut merged.self_service.default_input_mode, config.self_service.default_input_mode, &source_file, &mut defined_in)?;
+
+        merged.self_service.sections.extend(config.self_service.sections);
+    }
+
+    merged.self_service.apply_default_input_mode();
+
+    Ok(merged)
+}
+
+/// Set a block that can only be defined in one file, `defined_in` is the file of each block already set
+fn merge_once<T>(
+    name: &'static str,
+    merged: &mut Option<T>,
+    value: Option<T>,
+    source_file: &str,
+    defined_in: &mut BTreeMap<&'static str, String>,
+) -> Result<(), String> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+
+    if let Some(other_file) = defined_in.get(name) {
+        return Err(format!("{} is defined in '{}' and '{}'", name, other_file, source_file));
+    }
+
+    defined_in.insert(name, source_file.to_string());
+    *merged = Some(value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_yaml::Value;
+
+    use crate::config_files::{interpolate_env, merge_config_files, read_config_files, wildcard_match};
+    use crate::yaml_config::InputMode;
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn section(slug: &str) -> String {
+        format!("self_service:\n  sections:\n    - slug: {}\n      name: {}\n", slug, slug)
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.yaml", "team-a.yaml"));
+        assert!(wildcard_match("team-?.yaml", "team-a.yaml"));
+        assert!(wildcard_match("*", "team-a.yaml"));
+        assert!(!wildcard_match("*.yaml", "team-a.yml"));
+        assert!(!wildcard_match("team-?.yaml", "team-ab.yaml"));
+    }
+
+    #[test]
+    fn test_read_and_merge_includes() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "config.yaml", "include:\n  - sections/*.yaml\n  - sections/team-a.yaml\nself_service:\n  retention:\n    max_age_days: 30\n");
+        write(dir.path(), "sections/team-b.yaml", &section("team-b"));
+        write(dir.path(), "sections/team-a.yaml", &section("team-a"));
+        write(dir.path(), "sections/README.md", "not a config file");
+
+        let files = read_config_files(&dir.path().join("config.yaml")).unwrap();
+
+        // the file included twice is read once
+        let names = files.iter().map(|file| file.path.file_name().unwrap().to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["config.yaml", "team-a.yaml", "team-b.yaml"]);
+
+        let config = merge_config_files(&files).unwrap();
+        let slugs = config.self_service.sections.iter().map(|section| section.slug.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(slugs, vec!["team-a", "team-b"]);
+        assert!(config.self_service.sections[0].source_file.as_ref().unwrap().ends_with("team-a.yaml"));
+        assert_eq!(config.self_service.retention.unwrap().max_age_days, Some(30));
+    }
+
+    #[test]
+    fn test_read_directory() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "b.yml", &section("b"));
+        write(dir.path(), "a.yaml", &section("a"));
+
+        let config = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap();
+        let slugs = config.self_service.sections.iter().map(|section| section.slug.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(slugs, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_merge_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "a.yaml", &section("shared"));
+        write(dir.path(), "b.yaml", &section("shared"));
+
+        let err = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap().validate().unwrap_err();
+        assert!(err.contains("section slug 'shared' is already used in") && err.contains("a.yaml") && err.contains("b.yaml"), "{}", err);
+
+        let retention = "self_service:\n  retention:\n    max_age_days: 30\n";
+        write(dir.path(), "a.yaml", retention);
+        write(dir.path(), "b.yaml", retention);
+
+        let err = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap_err();
+        assert!(err.contains("retention is defined in"), "{}", err);
+    }
+
+    #[test]
+    fn test_interpolate_env() {
+        let env = |name: &str| match name {
+            "PORT" => Some("8080".to_string()),
+            "EMPTY" => Some(String::new()),
+            "PASSWORD" => Some("it's: #not\n  yaml".to_string()),
+            _ => None,
+        };
+
+        let mut value = serde_yaml::from_str::<Value>("port: ${PORT}\nurl: ${DB_URL:-postgres://localhost/torii}\n\
+            name: ${EMPTY:-default}-${EMPTY}\npassword: ${PASSWORD} # ${UNSET} in a comment\nversion: '$${PORT}'\n\
+            validate:\n  - command: [sh, -c, 'echo ${HOME}']\n    env:\n      PORT: ${PORT}\n").unwrap();
+
+        interpolate_env(&mut value, &env).unwrap();
+
+        let expected = serde_yaml::from_str::<Value>("port: 8080\nurl: postgres://localhost/torii\nname: default-\n\
+            password: \"it's: #not\\n  yaml\"\nversion: '${PORT}'\n\
+            validate:\n  - command: [sh, -c, 'echo ${HOME}']\n    env:\n      PORT: 8080\n").unwrap();
+
+        assert_eq!(value, expected);
+
+        let mut value = serde_yaml::from_str::<Value>("a: 1\nsections:\n  - url: ${DB_URL}\n").unwrap();
+        let err = interpolate_env(&mut value, &env).unwrap_err();
+        assert!(err.starts_with("sections[0].url: environment variable 'DB_URL' is not set"), "{}", err);
+
+        let mut value = Value::String("${DB_URL".to_string());
+        assert!(interpolate_env(&mut value, &env).unwrap_err().contains("unterminated"));
+
+        let mut value = Value::String("${DB URL}".to_string());
+        assert!(interpolate_env(&mut value, &env).unwrap_err().contains("invalid variable name"));
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let config = merge_config_files(&read_config_files(Path::new("examples/config.yaml")).unwrap()).unwrap();
+        assert!(config.validate().is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "config.yaml", "server:\n  port: ${PORT:-9000}\n");
+
+        let config = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap();
+        assert_eq!(config.server.unwrap().port, Some(9000));
+
+        write(dir.path(), "config.yaml", "# the port\nserver:\n  port: ${PORT:-not a port}\n");
+
+        let err = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap_err();
+        assert!(err.ends_with("server.port: invalid type: string \"not a port\", expected u16"), "{}", err);
+    }
+
+    #[test]
+    fn test_default_input_mode() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "a.yaml", "self_service:\n  default_input_mode: stdin\n");
+        write(dir.path(), "b.yaml", r#"
+self_service:
+  sections:
+    - slug: b
+      name: B
+      actions:
+        - slug: create
+          name: Create
+          validate:
+            - command: [sh, check.sh]
+              input_mode: file
+          post_validate:
+            - command: [sh, create.sh]
+              rollback:
+                command: [sh, delete.sh]
+"#);
+
+        let config = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap();
+        let action = &config.self_service.sections[0].actions.as_ref().unwrap()[0];
+
+        assert_eq!(action.validate.as_ref().unwrap()[0].input_mode, Some(InputMode::File));
+        assert_eq!(action.post_validate.as_ref().unwrap()[0].input_mode, Some(InputMode::Stdin));
+        assert_eq!(action.post_validate.as_ref().unwrap()[0].rollback.as_ref().unwrap().input_mode, Some(InputMode::Stdin));
+
+        write(dir.path(), "b.yaml", "self_service:\n  default_input_mode: env\n");
+
+        let err = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap_err();
+        assert!(err.contains("default_input_mode is defined in"), "{}", err);
+    }
+
+    #[test]
+    fn test_merge_server_and_database() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "a.yaml", "server:\n  port: 8080\n");
+        write(dir.path(), "b.yaml", "database:\n  max_connections: 10\n");
+
+        let config = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap();
+
+        assert_eq!(config.server.unwrap().port, Some(8080));
+        assert_eq!(config.database.unwrap().max_connections, Some(10));
+
+        write(dir.path(), "b.yaml", "server:\n  port: 9090\n");
+
+        let err = merge_config_files(&read_config_files(dir.path()).unwrap()).unwrap_err();
+        assert!(err.contains("server is defined in"), "{}", err);
+    }
+
+    #[test]
+    fn test_missing_include() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "config.yaml", "include:\n  - missing.yaml\n");
+
+        assert!(read_config_files(&dir.path().join("config.yaml")).unwrap_err().contains("missing.yaml"));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+use crate::config_files::{merge_config_files, read_config_files};
+
+/// Check the config files and return all their problems, with the position of the invalid values:
+/// `file:line:column: path: message`
+pub fn lint_config(path: &Path) -> Vec<String> {
+    let files = match read_config_files(path) {
+        Ok(files) => files,
+        Err(err) => return vec![err],
+    };
+
+    // the parsing errors already contain their position, or the path of the invalid value once the file is interpolated
+    let config = match merge_config_files(&files) {
+        Ok(config) => config,
+        Err(err) => return vec![err],
+    };
+
+    let files_positions = files.iter()
+        .map(|file| (file.path.display().to_string(), yaml_positions(&String::from_utf8_lossy(&file.content))))
+        .collect::<Vec<_>>();
+
+    config.errors().into_iter()
+        .map(|error| {
+            // only the errors of the sections know their file, the others are in the file defining their path
+            let file_positions = match &error.file {
+                Some(file) => files_positions.iter().find(|(path, _)| path == file),
+                None => files_positions.iter().find(|(_, positions)| positions.contains_key(&error.path))
+                    .or(files_positions.first()),
+            };
+
+            match file_positions {
+                Some((file, positions)) => match find_position(positions, &error.path) {
+                    Some((line, column)) => format!("{}:{}:{}: {}: {}", file, line, column, error.path, error.message),
+                    None => format!("{}: {}: {}", file, error.path, error.message),
+                },
+                None => error.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Position of the value at the path, or of its closest parent when the value is missing
+fn find_position(positions: &BTreeMap<String, (usize, usize)>, path: &str) -> Option<(usize, usize)> {
+    let mut path = path;
+
+    loop {
+        if let Some(position) = positions.get(path) {
+            return Some(*position);
+        }
+
+        path = &path[..path.rfind(['.', '['])?];
+    }
+}
+
+/// Line and column of the keys and the sequence items of a YAML document, by path (e.g. `self_service.sections[0].slug`)
+fn yaml_positions(content: &str) -> BTreeMap<String, (usize, usize)> {
+    let mut receiver = PositionsReceiver::default();
+
+    // an invalid document is reported when it's parsed, the positions found until the error are kept
+    let _ = Parser::new_from_str(content).load(&mut receiver, false);
+
+    receiver.positions
+}
+
+enum Node {
+    /// `key` is the key read, whose value is expected next
+    Mapping { path: String, key: Option<String> },
+    /// `index` is the index of the next item
+    Sequence { path: String, index: usize },
+}
+
+#[derive(Default)]
+struct PositionsReceiver {
+    parents: Vec<Node>,
+    positions: BTreeMap<String, (usize, usize)>,
+}
+
+impl PositionsReceiver {
+    /// Path of the node starting at the marker, the position of the keys and of the items is recorded
+    fn node_path(&mut self, event: &Event, marker: Marker) -> String {
+        let position = (marker.line(), marker.col() + 1);
+
+        match self.parents.last_mut() {
+            None => String::new(),
+            Some(Node::Sequence { path, index }) => {
+                let item_path = format!("{}[{}]", path, index);
+                *index += 1;
+
+                // the marker of a mapping is not at its start, the mapping is located at its first key
+                if !matches!(event, Event::MappingStart(..)) {
+                    self.positions.entry(item_path.clone()).or_insert(position);
+                }
+
+                item_path
+            }
+            Some(Node::Mapping { path, key }) => match key.take() {
+                Some(key) => join_path(path, &key),
+                None => {
+                    // only the scalar keys can be referenced by a path
+                    let name = match event {
+                        Event::Scalar(value, ..) => value.clone(),
+                        _ => String::new(),
+                    };
+
+                    let key_path = join_path(path, &name);
+                    *key = Some(name);
+
+                    if !path.is_empty() {
+                        self.positions.entry(path.clone()).or_insert(position);
+                    }
+
+                    self.positions.entry(key_path.clone()).or_insert(position);
+                    key_path
+                }
+            },
+        }
+    }
+}
+
+impl MarkedEventReceiver for PositionsReceiver {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        match event {
+            Event::Scalar(..) | Event::Alias(..) => {
+                self.node_path(&event, marker);
+            }
+            Event::MappingStart(..) => {
+                let path = self.node_path(&event, marker);
+                self.parents.push(Node::Mapping { path, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let path = self.node_path(&event, marker);
+                self.parents.push(Node::Sequence { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.parents.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    match path.is_empty() {
+        true => key.to_string(),
+        false => format!("{}.{}", path, key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::config_lint::{find_position, lint_config, yaml_positions};
+
+    const CONFIG: &str = r#"self_service:
+  sections:
+    - slug: default
+      name: Default
+      actions:
+        - slug: create
+          name: Create
+          fields:
+            - slug: name
+              title: Name
+              type: text
+            - slug: name
+              title: ""
+              type: text
+    - slug: Invalid Slug
+      name: ""
+"#;
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        std::fs::write(dir.join(name), content).unwrap();
+    }
+
+    #[test]
+    fn test_yaml_positions() {
+        let positions = yaml_positions(CONFIG);
+
+        assert_eq!(positions.get("self_service"), Some(&(1, 1)));
+        assert_eq!(positions.get("self_service.sections[0]"), Some(&(3, 7)));
+        assert_eq!(positions.get("self_service.sections[0].actions[0].fields[1].title"), Some(&(13, 15)));
+        assert_eq!(positions.get("self_service.sections[1].name"), Some(&(16, 7)));
+
+        // a missing value is located at its parent
+        assert_eq!(find_position(&positions, "self_service.sections[0].actions[0].fields[1].default"), Some((12, 15)));
+    }
+
+    #[test]
+    fn test_lint_config() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "config.yaml", CONFIG);
+
+        let file = dir.path().join("config.yaml").display().to_string();
+        let problems = lint_config(&dir.path().join("config.yaml"));
+
+        assert_eq!(problems, vec![
+            format!("{}:12:15: self_service.sections[0].actions[0].fields[1]: title is empty", file),
+            format!("{}:12:15: self_service.sections[0].actions[0].fields[1].slug: field slug 'name' is used more than once", file),
+            format!("{}:15:7: self_service.sections[1].slug: slug should only contains alphanumeric characters and dashes", file),
+            format!("{}:16:7: self_service.sections[1].name: name is empty", file),
+        ]);
+    }
+
+    #[test]
+    fn test_lint_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let section = "self_service:\n  sections:\n    - slug: shared\n      name: Shared\n";
+
+        write(dir.path(), "a.yaml", section);
+        write(dir.path(), "b.yaml", &format!("# team b\n{}", section));
+
+        let problems = lint_config(dir.path());
+
+        assert_eq!(problems, vec![format!(
+            "{}:4:7: self_service.sections[0].slug: section slug 'shared' is already used in '{}'",
+            dir.path().join("b.yaml").display(),
+            dir.path().join("a.yaml").display(),
+        )]);
+
+        write(dir.path(), "b.yaml", "self_service: [");
+
+        let problems = lint_config(dir.path());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("b.yaml") && problems[0].contains("line 2 column 1"), "{}", problems[0]);
+
+        write(dir.path(), "b.yaml", "self_service:\n  sections:\n    - slug: b\n      name: B\n      actions: ${ACTIONS:-none}\n");
+
+        let problems = lint_config(dir.path());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("b.yaml") && problems[0].contains("self_service.sections[0].actions: invalid type"), "{}", problems[0]);
+    }
+}
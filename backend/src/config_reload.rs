@@ -0,0 +1,453 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{Extension, Json};
+use axum::extract::Request;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::config_files::{ConfigFile, merge_config_files, read_config_files};
+use crate::constants::CONFIG_WATCH_INTERVAL_IN_SECONDS;
+use crate::self_service::ResultResponse;
+use crate::yaml_config::{SelfServiceSectionActionYamlConfig, SelfServiceSectionYamlConfig, YamlConfig};
+
+/// Read, merge and validate the config files, return the config with the checksum of the files
+pub fn load_config(path: &Path) -> Result<(YamlConfig, String), String> {
+    let files = read_config_files(path)?;
+    let config = merge_config_files(&files)?;
+
+    config.validate().map_err(|err| format!("failed to validate config: {}", err))?;
+
+    Ok((config, checksum(&files)))
+}
+
+fn checksum(files: &[ConfigFile]) -> String {
+    let mut hasher = Sha256::new();
+
+    for file in files {
+        hasher.update(file.path.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(&file.content);
+        hasher.update([0]);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// The config in use, swapped as a whole when the file is reloaded.
+/// A run or a validation keeps the config of its action from when it was queued, a reload does not change it.
+pub struct SharedConfig {
+    path: PathBuf,
+    /// version of the config, incremented on each reload, and the config
+    current: watch::Sender<(u64, Arc<YamlConfig>)>,
+    /// checksum of the loaded file, reloads are serialized by this lock
+    checksum: Mutex<String>,
+    /// error of the last reload, cleared by a successful one
+    last_reload_error: Mutex<Option<String>>,
+}
+
+impl SharedConfig {
+    pub fn new(path: PathBuf, config: YamlConfig, checksum: String) -> Self {
+        SharedConfig {
+            path,
+            current: watch::Sender::new((1, Arc::new(config))),
+            checksum: Mutex::new(checksum),
+            last_reload_error: Mutex::new(None),
+        }
+    }
+
+    pub fn current(&self) -> Arc<YamlConfig> {
+        self.current.borrow().1.clone()
+    }
+
+    pub fn current_with_version(&self) -> (u64, Arc<YamlConfig>) {
+        self.current.borrow().clone()
+    }
+
+    /// Notified on each reload
+    pub fn subscribe(&self) -> watch::Receiver<(u64, Arc<YamlConfig>)> {
+        self.current.subscribe()
+    }
+
+    /// The config files failed to load on the last reload, the config in use is outdated
+    pub fn check(&self) -> Result<(), String> {
+        match self.last_reload_error.lock().map_err(|err| err.to_string())?.as_ref() {
+            Some(err) => Err(format!("the config files failed to reload, the previous config is in use: {}", err)),
+            None => Ok(()),
+        }
+    }
+
+    /// Load the file again and swap the config if it's valid, the config in use is kept otherwise
+    pub fn reload(&self) -> Result<ConfigDiff, String> {
+        let result = self.swap();
+
+        if let Ok(mut last_reload_error) = self.last_reload_error.lock() {
+            *last_reload_error = result.as_ref().err().cloned();
+        }
+
+        result
+    }
+
+    fn swap(&self) -> Result<ConfigDiff, String> {
+        let mut current_checksum = self.checksum.lock().map_err(|err| err.to_string())?;
+
+        let (config, checksum) = load_config(&self.path)?;
+        let (version, previous) = self.current_with_version();
+        let diff = ConfigDiff::new(&previous, &config);
+
+        *current_checksum = checksum;
+
+        if previous.as_ref() == &config {
+            info!("config reloaded: no change");
+            return Ok(diff);
+        }
+
+        if previous.server != config.server || previous.database != config.database {
+            warn!("the server and database settings changed, they are applied on restart");
+        }
+
+        if previous.self_service.max_concurrent_validations != config.self_service.max_concurrent_validations {
+            warn!("max_concurrent_validations changed, it is applied on restart");
+        }
+
+        self.current.send_replace((version + 1, Arc::new(config)));
+
+        info!("config reloaded (version {}): {}", version + 1, diff);
+
+        Ok(diff)
+    }
+
+    /// Reload the config on the blocking thread pool, reading and parsing the files must not stall the runtime
+    pub async fn reload_blocking(self: &Arc<Self>) -> Result<ConfigDiff, String> {
+        let shared_config = self.clone();
+
+        tokio::task::spawn_blocking(move || shared_config.reload()).await
+            .map_err(|err| format!("config reload task failed: {}", err))?
+    }
+
+    /// Reload the config if a file was changed, added or removed since the last load
+    fn reload_if_changed(&self, last_seen_checksum: &mut String) {
+        // a file can be missing while it's replaced, they are checked again on the next tick
+        let Ok(files) = read_config_files(&self.path) else {
+            return;
+        };
+
+        let checksum = checksum(&files);
+
+        if &checksum == last_seen_checksum {
+            return;
+        }
+
+        // an invalid file is not loaded again until it changes
+        *last_seen_checksum = checksum;
+
+        if self.checksum.lock().is_ok_and(|current_checksum| *current_checksum == *last_seen_checksum) {
+            return;
+        }
+
+        info!("config files of '{}' changed, reloading them", self.path.display());
+
+        if let Err(err) = self.reload() {
+            error!("config not reloaded, the current config is kept: {}", err);
+        }
+    }
+}
+
+/// Sections and actions added, removed and changed by a reload, the actions are identified by `section/action`
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub added_sections: Vec<String>,
+    pub removed_sections: Vec<String>,
+    pub changed_sections: Vec<String>,
+    pub added_actions: Vec<String>,
+    pub removed_actions: Vec<String>,
+    pub changed_actions: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn new(previous: &YamlConfig, config: &YamlConfig) -> Self {
+        let mut diff = ConfigDiff::default();
+
+        // the actions are compared on their own, a section only changes with its name or description
+        let section_key = |section: &SelfServiceSectionYamlConfig| (section.name.clone(), section.description.clone());
+
+        diff_by_slug(
+            &sections_by_slug(previous).into_iter().map(|(slug, section)| (slug, section_key(section))).collect(),
+            &sections_by_slug(config).into_iter().map(|(slug, section)| (slug, section_key(section))).collect(),
+            &mut diff.added_sections,
+            &mut diff.removed_sections,
+            &mut diff.changed_sections,
+        );
+
+        diff_by_slug(
+            &actions_by_slug(previous),
+            &actions_by_slug(config),
+            &mut diff.added_actions,
+            &mut diff.removed_actions,
+            &mut diff.changed_actions,
+        );
+
+        diff
+    }
+}
+
+impl std::fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let changes = [
+            ("sections added", &self.added_sections),
+            ("sections removed", &self.removed_sections),
+            ("sections changed", &self.changed_sections),
+            ("actions added", &self.added_actions),
+            ("actions removed", &self.removed_actions),
+            ("actions changed", &self.changed_actions),
+        ];
+
+        let changes = changes.iter()
+            .filter(|(_, slugs)| !slugs.is_empty())
+            .map(|(name, slugs)| format!("{} {:?}", name, slugs))
+            .collect::<Vec<_>>();
+
+        match changes.is_empty() {
+            true => write!(f, "no section or action changed"),
+            false => write!(f, "{}", changes.join(", ")),
+        }
+    }
+}
+
+fn sections_by_slug(config: &YamlConfig) -> BTreeMap<String, &SelfServiceSectionYamlConfig> {
+    config.self_service.sections.iter().map(|section| (section.slug.clone(), section)).collect()
+}
+
+fn actions_by_slug(config: &YamlConfig) -> BTreeMap<String, &SelfServiceSectionActionYamlConfig> {
+    config.self_service.sections.iter()
+        .flat_map(|section| section.actions.iter().flatten().map(move |action| (format!("{}/{}", section.slug, action.slug), action)))
+        .collect()
+}
+
+fn diff_by_slug<T: PartialEq>(
+    previous: &BTreeMap<String, T>,
+    current: &BTreeMap<String, T>,
+    added: &mut Vec<String>,
+    removed: &mut Vec<String>,
+    changed: &mut Vec<String>,
+) {
+    for (slug, value) in current {
+        match previous.get(slug) {
+            None => added.push(slug.clone()),
+            Some(previous_value) if previous_value != value => changed.push(slug.clone()),
+            Some(_) => {}
+        }
+    }
+
+    removed.extend(previous.keys().filter(|slug| !current.contains_key(*slug)).cloned());
+}
+
+/// Reload the config when its files change, and on SIGHUP
+pub async fn config_watcher(shared_config: Arc<SharedConfig>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(CONFIG_WATCH_INTERVAL_IN_SECONDS));
+    let mut last_seen_checksum = shared_config.checksum.lock().map(|checksum| checksum.clone()).unwrap_or_default();
+
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .map_err(|err| error!("failed to listen to SIGHUP, the config is only reloaded on change: {}", err))
+        .ok();
+
+    loop {
+        #[cfg(unix)]
+        let hangup_received = async {
+            match hangup.as_mut() {
+                Some(hangup) => hangup.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+
+        #[cfg(not(unix))]
+        let hangup_received = std::future::pending::<Option<()>>();
+
+        tokio::select! {
+            _ = interval.tick() => {
+                let shared_config = shared_config.clone();
+
+                let checked = tokio::task::spawn_blocking(move || {
+                    shared_config.reload_if_changed(&mut last_seen_checksum);
+                    last_seen_checksum
+                }).await;
+
+                match checked {
+                    Ok(checksum) => last_seen_checksum = checksum,
+                    Err(err) => {
+                        error!("config watcher task failed: {}", err);
+                        last_seen_checksum = String::new();
+                    }
+                }
+            }
+            Some(_) = hangup_received => {
+                info!("SIGHUP received, reloading the config");
+
+                if let Err(err) = shared_config.reload_blocking().await {
+                    error!("config not reloaded, the current config is kept: {}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Reload the config files, an invalid config is refused and the current config is kept
+pub async fn reload_config(
+    Extension(shared_config): Extension<Arc<SharedConfig>>,
+) -> (StatusCode, Json<ResultResponse<ConfigDiff>>) {
+    match shared_config.reload_blocking().await {
+        Ok(diff) => (StatusCode::OK, Json(ResultResponse { message: None, result: Some(diff) })),
+        Err(err) => {
+            error!("config not reloaded, the current config is kept: {}", err);
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(ResultResponse { message: Some(err), result: None }))
+        }
+    }
+}
+
+/// Token required by the admin endpoints, they are disabled when it's not set
+#[derive(Clone)]
+pub struct AdminToken(pub Option<String>);
+
+/// Only let the requests with `Authorization: Bearer <admin token>` through
+pub async fn require_admin_token(Extension(admin_token): Extension<AdminToken>, req: Request, next: Next) -> Response {
+    let Some(admin_token) = admin_token.0 else {
+        return (StatusCode::FORBIDDEN, Json(ResultResponse::<()> {
+            message: Some("the admin endpoints are disabled, set --admin-token (ADMIN_TOKEN) to enable them".to_string()),
+            result: None,
+        })).into_response();
+    };
+
+    if !is_authorized(&admin_token, req.headers().get(header::AUTHORIZATION)) {
+        return (StatusCode::UNAUTHORIZED, Json(ResultResponse::<()> {
+            message: Some("missing or invalid admin token".to_string()),
+            result: None,
+        })).into_response();
+    }
+
+    next.run(req).await
+}
+
+/// The token is compared in constant time, to not leak how much of it matches
+fn is_authorized(admin_token: &str, authorization: Option<&HeaderValue>) -> bool {
+    let Some(token) = authorization.and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+
+    token.len() == admin_token.len()
+        && token.bytes().zip(admin_token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use crate::config_reload::{ConfigDiff, is_authorized, load_config, SharedConfig};
+
+    const CONFIG: &str = r#"
+self_service:
+  sections:
+    - slug: default
+      name: Default
+      actions:
+        - slug: create
+          name: Create
+          fields: []
+        - slug: delete
+          name: Delete
+          fields: []
+    - slug: other
+      name: Other
+"#;
+
+    #[test]
+    fn test_config_diff() {
+        let previous = serde_yaml::from_str(CONFIG).unwrap();
+        let config = serde_yaml::from_str(r#"
+self_service:
+  sections:
+    - slug: default
+      name: Default section
+      actions:
+        - slug: create
+          name: Create an environment
+        - slug: update
+          name: Update
+    - slug: new
+      name: New
+"#).unwrap();
+
+        let diff = ConfigDiff::new(&previous, &config);
+
+        assert_eq!(diff.added_sections, vec!["new"]);
+        assert_eq!(diff.removed_sections, vec!["other"]);
+        assert_eq!(diff.changed_sections, vec!["default"]);
+        assert_eq!(diff.added_actions, vec!["default/update"]);
+        assert_eq!(diff.removed_actions, vec!["default/delete"]);
+        assert_eq!(diff.changed_actions, vec!["default/create"]);
+
+        assert_eq!(ConfigDiff::new(&previous, &previous), ConfigDiff::default());
+    }
+
+    #[test]
+    fn test_reload() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), CONFIG).unwrap();
+
+        let (config, checksum) = load_config(file.path()).unwrap();
+        let shared_config = SharedConfig::new(file.path().to_path_buf(), config, checksum);
+        let changes = shared_config.subscribe();
+
+        // an invalid config is refused
+        std::fs::write(file.path(), "self_service:\n  sections:\n    - slug: Invalid Slug\n      name: Invalid\n").unwrap();
+        assert!(shared_config.reload().is_err());
+        assert_eq!(shared_config.current_with_version().0, 1);
+        assert!(!changes.has_changed().unwrap());
+        assert!(shared_config.check().is_err());
+
+        std::fs::write(file.path(), CONFIG.replace("name: Delete", "name: Delete an environment")).unwrap();
+        let diff = shared_config.reload().unwrap();
+
+        assert_eq!(diff.changed_actions, vec!["default/delete"]);
+        assert_eq!(shared_config.current_with_version().0, 2);
+        assert!(shared_config.check().is_ok());
+        assert!(changes.has_changed().unwrap());
+
+        let actions = shared_config.current().self_service.sections[0].actions.clone().unwrap();
+        assert_eq!(actions[1].name, "Delete an environment");
+    }
+
+    #[test]
+    fn test_reload_if_changed() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), CONFIG).unwrap();
+
+        let (config, checksum) = load_config(file.path()).unwrap();
+        let mut last_seen_checksum = checksum.clone();
+        let shared_config = SharedConfig::new(file.path().to_path_buf(), config, checksum);
+
+        shared_config.reload_if_changed(&mut last_seen_checksum);
+        assert_eq!(shared_config.current_with_version().0, 1);
+
+        std::fs::write(file.path(), CONFIG.replace("name: Other", "name: Other section")).unwrap();
+        shared_config.reload_if_changed(&mut last_seen_checksum);
+        assert_eq!(shared_config.current_with_version().0, 2);
+        assert_eq!(shared_config.current().self_service.sections[1].name, "Other section");
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        assert!(is_authorized("secret", Some(&HeaderValue::from_static("Bearer secret"))));
+        assert!(!is_authorized("secret", Some(&HeaderValue::from_static("Bearer secreT"))));
+        assert!(!is_authorized("secret", Some(&HeaderValue::from_static("Bearer secret2"))));
+        assert!(!is_authorized("secret", Some(&HeaderValue::from_static("secret"))));
+        assert!(!is_authorized("secret", None));
+    }
+}
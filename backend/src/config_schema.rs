@@ -0,0 +1,91 @@
+use axum::Json;
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+
+use crate::yaml_config::YamlConfig;
+
+/// JSON Schema of the config files, generated from the serde model, for the completion and the validation in the editors
+pub fn config_schema() -> RootSchema {
+    schema_for!(YamlConfig)
+}
+
+pub async fn get_config_schema() -> Json<RootSchema> {
+    Json(config_schema())
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonschema::JSONSchema;
+    use serde_json::{json, Value};
+
+    use crate::config_schema::config_schema;
+    use crate::yaml_config::{ActionFieldType, InputMode, YamlConfig};
+
+    fn compiled_schema() -> JSONSchema {
+        JSONSchema::compile(&serde_json::to_value(config_schema()).unwrap()).unwrap()
+    }
+
+    fn errors(schema: &JSONSchema, config: &Value) -> Vec<String> {
+        match schema.validate(config) {
+            Ok(_) => vec![],
+            Err(errors) => errors.map(|error| format!("{}: {}", error.instance_path, error)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_examples_match_schema() {
+        let schema = compiled_schema();
+
+        for entry in std::fs::read_dir("examples").unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.extension().is_some_and(|extension| extension == "yaml") {
+                let content = std::fs::read_to_string(&path).unwrap();
+
+                // the schema and the serde model must agree on the examples
+                serde_yaml::from_str::<YamlConfig>(&content).unwrap();
+                let config = serde_yaml::from_str::<Value>(&content).unwrap();
+
+                assert_eq!(errors(&schema, &config), Vec::<String>::new(), "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn test_schema_enums() {
+        let schema = compiled_schema();
+
+        let config = |field_type: &str, input_mode: &str, executor: Value| json!({
+            "self_service": {
+                "sections": [{
+                    "slug": "default",
+                    "name": "Default",
+                    "actions": [{
+                        "slug": "create",
+                        "name": "Create",
+                        "fields": [{ "slug": "name", "title": "Name", "type": field_type }],
+                        "validate": [{ "command": ["true"], "input_mode": input_mode, "executor": executor }],
+                    }],
+                }],
+            },
+        });
+
+        // all the values accepted by serde are accepted by the schema
+        for field_type in ["text", "textarea", "number", "boolean", "date", "datetime", "time", "list"] {
+            serde_json::from_value::<ActionFieldType>(json!(field_type)).unwrap();
+            assert!(errors(&schema, &config(field_type, "stdin", json!({ "type": "local" }))).is_empty(), "{}", field_type);
+        }
+
+        for input_mode in ["argv", "stdin", "file", "env"] {
+            serde_json::from_value::<InputMode>(json!(input_mode)).unwrap();
+            assert!(errors(&schema, &config("text", input_mode, json!({ "type": "local" }))).is_empty(), "{}", input_mode);
+        }
+
+        let container = json!({ "type": "container", "image": "alpine:3", "mounts": [{ "source": "/data", "target": "/data" }] });
+        assert!(errors(&schema, &config("text", "argv", container)).is_empty());
+
+        assert!(!errors(&schema, &config("color", "argv", json!({ "type": "local" }))).is_empty());
+        assert!(!errors(&schema, &config("text", "pipe", json!({ "type": "local" }))).is_empty());
+        assert!(!errors(&schema, &config("text", "argv", json!({ "type": "container" }))).is_empty());
+    }
+}
@@ -9,3 +9,30 @@ pub const BANNER: &str = r#"
    ╚═╝    ╚═════╝ ╚═╝  ╚═╝╚═╝╚═╝
 "#;
 pub const DEFAULT_TIMEOUT_IN_SECONDS: u64 = 1800;
+pub const TORII_ENV_PREFIX: &str = "TORII_";
+pub const DEFAULT_CONTAINER_RUNTIME_SOCKET: &str = "/var/run/docker.sock";
+/// how long a successful validation can be used to execute an action
+pub const VALIDATION_TTL_IN_SECONDS: i64 = 3600;
+pub const DEFAULT_RUNS_PAGE_SIZE: i64 = 50;
+pub const MAX_RUNS_PAGE_SIZE: i64 = 500;
+pub const DEFAULT_RETENTION_INTERVAL_IN_SECONDS: u64 = 3600;
+/// number of runs pruned, and archived, per transaction by the retention job
+pub const RETENTION_BATCH_SIZE: i64 = 500;
+/// validations running at the same time when `max_concurrent_validations` is not set
+pub const DEFAULT_MAX_CONCURRENT_VALIDATIONS: usize = 8;
+/// how often an idle worker reports it is alive
+pub const WORKER_HEARTBEAT_INTERVAL_IN_SECONDS: u64 = 5;
+/// an idle worker without heartbeat for this long is considered stuck
+pub const WORKER_HEARTBEAT_TIMEOUT_IN_SECONDS: i64 = 30;
+/// a busy worker is considered stuck this long after its task should have timed out (killing the processes, saving the results)
+pub const WORKER_BUSY_GRACE_IN_SECONDS: i64 = 60;
+/// maximum duration of a readiness check
+pub const HEALTH_CHECK_TIMEOUT_IN_SECONDS: u64 = 2;
+/// how often the config file is checked for changes
+pub const CONFIG_WATCH_INTERVAL_IN_SECONDS: u64 = 2;
+/// how long a dry run request waits after the timeout of the action, for the run to kill its processes and save its results
+pub const DRY_RUN_GRACE_IN_SECONDS: u64 = 10;
+/// a run or a validation is taken over by another replica once its backend did not renew its lease for this long
+pub const LEASE_DURATION_IN_SECONDS: i64 = 60;
+/// how often a backend renews the leases of its runs and validations and takes over the ones whose lease expired
+pub const LEASE_RENEW_INTERVAL_IN_SECONDS: u64 = 15;
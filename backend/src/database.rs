@@ -1,44 +1,12 @@
 use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
-use sqlx::{Executor, Pool, Postgres};
+use sqlx::{PgConnection, Pool, Postgres, QueryBuilder};
 use sqlx::types::Uuid;
 
+use crate::constants::LEASE_DURATION_IN_SECONDS;
 use crate::errors::QError;
 
-const DB_SCHEMA: &str = r#"
--- create status enum if it doesn't exist
-DO
-$$
-    BEGIN
-        IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'status') THEN
-            CREATE TYPE status AS ENUM (
-                'QUEUED',
-                'RUNNING',
-                'SUCCESS',
-                'FAILURE'
-                );
-        END IF;
-    END
-$$;
-
--- create a new flat table to store action runs
-CREATE TABLE IF NOT EXISTS self_service_runs
-(
-    id            UUID PRIMARY KEY DEFAULT gen_random_uuid() NOT NULL,
-    created_at    TIMESTAMP        DEFAULT CURRENT_TIMESTAMP NOT NULL,
-    updated_at    TIMESTAMP        DEFAULT CURRENT_TIMESTAMP NOT NULL,
-    section_slug  VARCHAR(255)                               NOT NULL,
-    action_slug   VARCHAR(255)                               NOT NULL,
-    status        status                                     NOT NULL,
-    input_payload JSONB DEFAULT '{}'::jsonb                  NOT NULL,
-    tasks         JSONB DEFAULT '{}'::jsonb                  NOT NULL
-);
-
-CREATE INDEX IF NOT EXISTS self_service_runs_section_slug_idx ON self_service_runs (section_slug);
-CREATE INDEX IF NOT EXISTS self_service_runs_action_slug_idx ON self_service_runs (action_slug);
-"#;
-
 #[derive(sqlx::FromRow)]
 pub struct SelfServiceRun {
     id: Uuid,
@@ -47,19 +15,30 @@ pub struct SelfServiceRun {
     section_slug: String,
     action_slug: String,
     status: Status,
-    input_payload: serde_json::Value,
-    tasks: serde_json::Value,
+    /// None when the run is listed as a summary
+    input_payload: Option<serde_json::Value>,
+    /// None when the run is listed as a summary
+    tasks: Option<serde_json::Value>,
+    /// only the tasks supporting it were executed, to show what the run would do
+    dry_run: bool,
+    triggered_by: Option<String>,
 }
 
 #[derive(sqlx::Type, Clone, Serialize, Deserialize, Debug)]
 #[sqlx(rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Status {
-    // update schema.sql when adding new status
+    // add a migration when adding new status
     Queued,
     Running,
     Success,
     Failure,
+    /// a task failed and the rollback of the previous tasks succeeded
+    RolledBack,
+    /// a task failed and the rollback of at least one of the previous tasks failed
+    RollbackFailed,
+    /// a task or the whole run exceeded its timeout - the outcome of the rollback, if any, is given apart
+    TimedOut,
 }
 
 impl SelfServiceRun {
@@ -73,12 +52,51 @@ impl SelfServiceRun {
             status: self.status.clone(),
             input_payload: self.input_payload.clone(),
             tasks: self.tasks.clone(),
+            dry_run: self.dry_run,
+            triggered_by: self.triggered_by.clone(),
         }
     }
 
+    /// Position of the run in a listing, to get the next page
+    pub fn cursor(&self) -> String {
+        format!("{}_{}", self.created_at.and_utc().timestamp_micros(), self.id)
+    }
+
     pub fn id(&self) -> String {
         self.id.to_string()
     }
+
+    pub fn uuid(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn section_slug(&self) -> &str {
+        &self.section_slug
+    }
+
+    pub fn action_slug(&self) -> &str {
+        &self.action_slug
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    pub fn input_payload(&self) -> &serde_json::Value {
+        self.input_payload.as_ref().unwrap_or(&serde_json::Value::Null)
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn triggered_by(&self) -> Option<&str> {
+        self.triggered_by.as_deref()
+    }
+
+    pub fn tasks(&self) -> &serde_json::Value {
+        self.tasks.as_ref().unwrap_or(&serde_json::Value::Null)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -89,127 +107,618 @@ pub struct SelfServiceRunJson {
     pub section_slug: String,
     pub action_slug: String,
     pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_payload: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tasks: Option<serde_json::Value>,
+    pub dry_run: bool,
+    pub triggered_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Filters and page of a run listing, the runs are sorted by creation date
+#[derive(Debug, Default)]
+pub struct RunFilters {
+    pub section_slug: Option<String>,
+    pub action_slug: Option<String>,
+    pub status: Option<Status>,
+    pub triggered_by: Option<String>,
+    pub created_after: Option<chrono::NaiveDateTime>,
+    pub created_before: Option<chrono::NaiveDateTime>,
+    /// JSON object contained in the input payload of the runs
+    pub payload: Option<serde_json::Value>,
+    /// creation date and id of the last run of the previous page
+    pub cursor: Option<(chrono::NaiveDateTime, Uuid)>,
+    pub sort: SortOrder,
+    pub limit: i64,
+    pub include_input_payload: bool,
+    pub include_tasks: bool,
+}
+
+impl RunFilters {
+    /// Parse a cursor returned by `SelfServiceRun::cursor`
+    pub fn parse_cursor(cursor: &str) -> Result<(chrono::NaiveDateTime, Uuid), String> {
+        let (micros, id) = cursor.split_once('_').ok_or_else(|| format!("invalid cursor '{}'", cursor))?;
+
+        let created_at = micros.parse::<i64>().ok()
+            .and_then(chrono::DateTime::from_timestamp_micros)
+            .ok_or_else(|| format!("invalid cursor '{}'", cursor))?;
+
+        let id = Uuid::from_str(id).map_err(|_| format!("invalid cursor '{}'", cursor))?;
+
+        Ok((created_at.naive_utc(), id))
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct SelfServiceValidation {
+    id: Uuid,
+    created_at: chrono::NaiveDateTime,
+    updated_at: chrono::NaiveDateTime,
+    section_slug: String,
+    action_slug: String,
+    status: Status,
+    payload_hash: String,
+    input_payload: serde_json::Value,
+    tasks: serde_json::Value,
+}
+
+impl SelfServiceValidation {
+    pub fn to_json(&self) -> SelfServiceValidationJson {
+        SelfServiceValidationJson {
+            id: self.id.to_string(),
+            created_at: self.created_at.to_string(),
+            updated_at: self.updated_at.to_string(),
+            section_slug: self.section_slug.clone(),
+            action_slug: self.action_slug.clone(),
+            status: self.status.clone(),
+            payload_hash: self.payload_hash.clone(),
+            input_payload: self.input_payload.clone(),
+            tasks: self.tasks.clone(),
+        }
+    }
+
+    pub fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    pub fn section_slug(&self) -> &str {
+        &self.section_slug
+    }
+
+    pub fn action_slug(&self) -> &str {
+        &self.action_slug
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    pub fn payload_hash(&self) -> &str {
+        &self.payload_hash
+    }
+
+    pub fn tasks(&self) -> &serde_json::Value {
+        &self.tasks
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SelfServiceValidationJson {
+    pub id: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub section_slug: String,
+    pub action_slug: String,
+    pub status: Status,
+    pub payload_hash: String,
     pub input_payload: serde_json::Value,
     pub tasks: serde_json::Value,
 }
 
+#[derive(sqlx::FromRow)]
+pub struct SelfServiceRunLog {
+    id: i64,
+    run_id: Uuid,
+    created_at: chrono::NaiveDateTime,
+    is_stderr: Option<bool>,
+    message: String,
+}
+
+impl SelfServiceRunLog {
+    pub fn run_id(&self) -> Uuid {
+        self.run_id
+    }
+
+    pub fn to_json(&self) -> SelfServiceRunLogJson {
+        SelfServiceRunLogJson {
+            id: self.id.to_string(),
+            created_at: self.created_at.to_string(),
+            is_stderr: self.is_stderr,
+            message: self.message.clone(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SelfServiceRunLogJson {
     pub id: String,
     pub created_at: String,
-    pub is_stderr: bool,
+    /// null when the stream of the line is unknown, e.g. the logs of a Kubernetes pod mix stdout and stderr
+    pub is_stderr: Option<bool>,
     pub message: String,
 }
 
-/// Initialize the database by creating the tables
-pub async fn init_database(pg_pool: &Pool<Postgres>) -> Result<(), QError> {
-    // execute SQL schema
-    let _ = pg_pool.execute(DB_SCHEMA).await?;
+/// List the runs matching the filters, one more than the limit to tell whether there is a next page
+pub async fn list_self_service_runs(
+    pg_pool: &Pool<Postgres>,
+    filters: &RunFilters,
+) -> Result<Vec<SelfServiceRun>, QError> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT id, created_at, updated_at, section_slug, action_slug, status, dry_run, triggered_by, "
+    );
 
-    Ok(())
+    query.push(if filters.include_input_payload { "input_payload, " } else { "NULL::jsonb AS input_payload, " });
+    query.push(if filters.include_tasks { "tasks " } else { "NULL::jsonb AS tasks " });
+    query.push("FROM self_service_runs WHERE TRUE");
+
+    if let Some(section_slug) = &filters.section_slug {
+        query.push(" AND section_slug = ").push_bind(section_slug);
+    }
+
+    if let Some(action_slug) = &filters.action_slug {
+        query.push(" AND action_slug = ").push_bind(action_slug);
+    }
+
+    if let Some(status) = &filters.status {
+        query.push(" AND status = ").push_bind(status.clone());
+    }
+
+    if let Some(triggered_by) = &filters.triggered_by {
+        query.push(" AND triggered_by = ").push_bind(triggered_by);
+    }
+
+    if let Some(created_after) = filters.created_after {
+        query.push(" AND created_at >= ").push_bind(created_after);
+    }
+
+    if let Some(created_before) = filters.created_before {
+        query.push(" AND created_at < ").push_bind(created_before);
+    }
+
+    if let Some(payload) = &filters.payload {
+        query.push(" AND input_payload @> ").push_bind(payload);
+    }
+
+    let (operator, order) = match filters.sort {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+
+    if let Some((created_at, id)) = filters.cursor {
+        query.push(format!(" AND (created_at, id) {} (", operator))
+            .push_bind(created_at)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+
+    query.push(format!(" ORDER BY created_at {}, id {} LIMIT ", order, order)).push_bind(filters.limit + 1);
+
+    Ok(query.build_query_as::<SelfServiceRun>().fetch_all(pg_pool).await?)
 }
 
-pub async fn list_self_service_runs_by_section_and_action_slugs(
+pub async fn insert_self_service_run(
     pg_pool: &Pool<Postgres>,
     section_slug: &str,
     action_slug: &str,
-) -> Result<Vec<SelfServiceRun>, QError> {
+    input_payload: &serde_json::Value,
+    dry_run: bool,
+    triggered_by: Option<&str>,
+    owner: &str,
+) -> Result<SelfServiceRun, QError> {
+    // the tasks are added by the background worker
     Ok(
         sqlx::query_as::<_, SelfServiceRun>(
             r#"
-            SELECT *
-            FROM self_service_runs
-            WHERE section_slug = $1 AND action_slug = $2
-            ORDER BY created_at DESC
+            INSERT INTO self_service_runs (section_slug, action_slug, status, input_payload, tasks, dry_run, triggered_by, owner, lease_until)
+            VALUES ($1, $2, $3, $4, '[]'::jsonb, $5, $6, $7, CURRENT_TIMESTAMP + make_interval(secs => $8))
+            RETURNING *
         "#
         )
             .bind(section_slug)
             .bind(action_slug)
-            .fetch_all(pg_pool)
+            .bind(Status::Queued)
+            .bind(input_payload)
+            .bind(dry_run)
+            .bind(triggered_by)
+            .bind(owner)
+            .bind(LEASE_DURATION_IN_SECONDS as f64)
+            .fetch_one(pg_pool)
             .await?
     )
 }
 
-pub async fn list_self_service_runs_by_section_slug(
+pub async fn get_self_service_run(
     pg_pool: &Pool<Postgres>,
-    section_slug: &str,
-) -> Result<Vec<SelfServiceRun>, QError> {
+    id: &str,
+) -> Result<Option<SelfServiceRun>, QError> {
+    let id = match Uuid::from_str(id) {
+        Ok(id) => id,
+        Err(_) => return Ok(None),
+    };
+
     Ok(
         sqlx::query_as::<_, SelfServiceRun>(
             r#"
             SELECT *
             FROM self_service_runs
-                WHERE section_slug = $1
-            ORDER BY created_at DESC
+            WHERE id = $1
         "#
         )
-            .bind(section_slug)
-            .fetch_all(pg_pool)
+            .bind(id)
+            .fetch_optional(pg_pool)
             .await?
     )
 }
 
-pub async fn list_self_service_runs(
+pub async fn update_self_service_run(
     pg_pool: &Pool<Postgres>,
-) -> Result<Vec<SelfServiceRun>, QError> {
+    id: &str,
+    status: Status,
+    tasks: &serde_json::Value,
+) -> Result<SelfServiceRun, QError> {
+    let id = Uuid::from_str(id).map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+
+    Ok(
+        sqlx::query_as::<_, SelfServiceRun>(
+            r#"
+            UPDATE self_service_runs
+            SET status = $1, tasks = $2
+            WHERE id = $3
+            RETURNING *
+        "#
+        )
+            .bind(status)
+            .bind(tasks)
+            .bind(id)
+            .fetch_one(pg_pool)
+            .await?
+    )
+}
+
+/// Extend the leases of the unfinished runs of the given owner
+pub async fn renew_self_service_run_leases(pg_pool: &Pool<Postgres>, owner: &str) -> Result<u64, QError> {
+    Ok(
+        sqlx::query(
+            r#"
+            UPDATE self_service_runs
+            SET lease_until = CURRENT_TIMESTAMP + make_interval(secs => $2)
+            WHERE owner = $1 AND status IN ('QUEUED', 'RUNNING')
+        "#
+        )
+            .bind(owner)
+            .bind(LEASE_DURATION_IN_SECONDS as f64)
+            .execute(pg_pool)
+            .await?
+            .rows_affected()
+    )
+}
+
+/// Take over the unfinished runs whose lease expired, oldest first.
+/// The rows locked by another replica are skipped, a run is claimed by a single owner.
+pub async fn claim_expired_self_service_runs(pg_pool: &Pool<Postgres>, owner: &str) -> Result<Vec<SelfServiceRun>, QError> {
     Ok(
         sqlx::query_as::<_, SelfServiceRun>(
             r#"
+            WITH claimed AS (
+                UPDATE self_service_runs
+                SET owner = $1, lease_until = CURRENT_TIMESTAMP + make_interval(secs => $2)
+                WHERE id IN (
+                    SELECT id
+                    FROM self_service_runs
+                    WHERE status IN ('QUEUED', 'RUNNING') AND (lease_until IS NULL OR lease_until < CURRENT_TIMESTAMP)
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING *
+            )
             SELECT *
+            FROM claimed
+            ORDER BY created_at ASC
+        "#
+        )
+            .bind(owner)
+            .bind(LEASE_DURATION_IN_SECONDS as f64)
+            .fetch_all(pg_pool)
+            .await?
+    )
+}
+
+/// Number of queued runs and age in seconds of the oldest one
+pub async fn get_queued_runs_stats(pg_pool: &Pool<Postgres>) -> Result<(i64, Option<f64>), QError> {
+    Ok(
+        sqlx::query_as::<_, (i64, Option<f64>)>(
+            r#"
+            SELECT COUNT(*), EXTRACT(EPOCH FROM LOCALTIMESTAMP - MIN(created_at))::FLOAT8
             FROM self_service_runs
-            ORDER BY created_at DESC
+            WHERE status = 'QUEUED'
         "#
         )
+            .fetch_one(pg_pool)
+            .await?
+    )
+}
+
+pub async fn insert_self_service_run_log(
+    pg_pool: &Pool<Postgres>,
+    run_id: &str,
+    is_stderr: Option<bool>,
+    message: &str,
+) -> Result<(), QError> {
+    let run_id = Uuid::from_str(run_id).map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+
+    let _ = sqlx::query(
+        r#"
+            INSERT INTO self_service_run_logs (run_id, is_stderr, message)
+            VALUES ($1, $2, $3)
+        "#
+    )
+        .bind(run_id)
+        .bind(is_stderr)
+        .bind(message)
+        .execute(pg_pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn list_self_service_run_logs(
+    pg_pool: &Pool<Postgres>,
+    run_id: &str,
+) -> Result<Vec<SelfServiceRunLog>, QError> {
+    let run_id = match Uuid::from_str(run_id) {
+        Ok(run_id) => run_id,
+        Err(_) => return Ok(vec![]),
+    };
+
+    Ok(
+        sqlx::query_as::<_, SelfServiceRunLog>(
+            r#"
+            SELECT id, run_id, created_at, is_stderr, message
+            FROM self_service_run_logs
+            WHERE run_id = $1
+            ORDER BY id ASC
+        "#
+        )
+            .bind(run_id)
             .fetch_all(pg_pool)
             .await?
     )
 }
 
-pub async fn insert_self_service_run(
+/// List the oldest finished runs to prune, the last failed runs of each action are kept.
+/// A run is pruned when it is older than the max age or beyond the max number of runs of its action.
+pub async fn list_self_service_runs_to_prune(
+    conn: &mut PgConnection,
+    max_age_days: Option<i64>,
+    max_runs_per_action: Option<i64>,
+    keep_last_failures: i64,
+    limit: i64,
+) -> Result<Vec<SelfServiceRun>, QError> {
+    Ok(
+        sqlx::query_as::<_, SelfServiceRun>(
+            r#"
+            WITH ranked AS (
+                SELECT id,
+                       status IN ('FAILURE', 'ROLLED_BACK', 'ROLLBACK_FAILED', 'TIMED_OUT') AS failed,
+                       ROW_NUMBER() OVER (
+                           PARTITION BY section_slug, action_slug
+                           ORDER BY created_at DESC, id DESC
+                       ) AS action_rank,
+                       ROW_NUMBER() OVER (
+                           PARTITION BY section_slug, action_slug, status IN ('FAILURE', 'ROLLED_BACK', 'ROLLBACK_FAILED', 'TIMED_OUT')
+                           ORDER BY created_at DESC, id DESC
+                       ) AS failed_rank
+                FROM self_service_runs
+            )
+            SELECT runs.*
+            FROM self_service_runs runs
+            JOIN ranked ON ranked.id = runs.id
+            WHERE runs.status NOT IN ('QUEUED', 'RUNNING')
+              AND (
+                  runs.created_at < CURRENT_TIMESTAMP - make_interval(days => $1::INT)
+                  OR ranked.action_rank > $2
+              )
+              AND NOT (ranked.failed AND ranked.failed_rank <= $3)
+            ORDER BY runs.created_at ASC, runs.id ASC
+            LIMIT $4
+        "#
+        )
+            .bind(max_age_days)
+            .bind(max_runs_per_action)
+            .bind(keep_last_failures)
+            .bind(limit)
+            .fetch_all(conn)
+            .await?
+    )
+}
+
+pub async fn list_self_service_run_logs_by_run_ids(
+    conn: &mut PgConnection,
+    run_ids: &[Uuid],
+) -> Result<Vec<SelfServiceRunLog>, QError> {
+    Ok(
+        sqlx::query_as::<_, SelfServiceRunLog>(
+            r#"
+            SELECT id, run_id, created_at, is_stderr, message
+            FROM self_service_run_logs
+            WHERE run_id = ANY($1)
+            ORDER BY id ASC
+        "#
+        )
+            .bind(run_ids)
+            .fetch_all(conn)
+            .await?
+    )
+}
+
+/// Delete the runs and their logs, return the number of deleted runs
+pub async fn delete_self_service_runs(
+    conn: &mut PgConnection,
+    ids: &[Uuid],
+) -> Result<u64, QError> {
+    Ok(
+        sqlx::query("DELETE FROM self_service_runs WHERE id = ANY($1)")
+            .bind(ids)
+            .execute(conn)
+            .await?
+            .rows_affected()
+    )
+}
+
+pub async fn insert_self_service_validation(
     pg_pool: &Pool<Postgres>,
     section_slug: &str,
     action_slug: &str,
-    status: Status,
+    payload_hash: &str,
     input_payload: &serde_json::Value,
-    tasks: &serde_json::Value,
-) -> Result<SelfServiceRun, QError> {
+    owner: &str,
+) -> Result<SelfServiceValidation, QError> {
     Ok(
-        sqlx::query_as::<_, SelfServiceRun>(
+        sqlx::query_as::<_, SelfServiceValidation>(
             r#"
-            INSERT INTO self_service_runs (section_slug, action_slug, status, input_payload, tasks)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO self_service_validations (section_slug, action_slug, status, payload_hash, input_payload, owner, lease_until)
+            VALUES ($1, $2, $3, $4, $5, $6, CURRENT_TIMESTAMP + make_interval(secs => $7))
             RETURNING *
         "#
         )
             .bind(section_slug)
             .bind(action_slug)
-            .bind(status)
+            .bind(Status::Queued)
+            .bind(payload_hash)
             .bind(input_payload)
-            .bind(tasks)
+            .bind(owner)
+            .bind(LEASE_DURATION_IN_SECONDS as f64)
             .fetch_one(pg_pool)
             .await?
     )
 }
 
-pub async fn update_self_service_run(
+pub async fn update_self_service_validation(
     pg_pool: &Pool<Postgres>,
     id: &str,
     status: Status,
     tasks: &serde_json::Value,
-) -> Result<SelfServiceRun, QError> {
+) -> Result<SelfServiceValidation, QError> {
+    let id = Uuid::from_str(id).map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+
     Ok(
-        sqlx::query_as::<_, SelfServiceRun>(
+        sqlx::query_as::<_, SelfServiceValidation>(
             r#"
-            UPDATE self_service_runs
-            SET status = $1, tasks = $2
+            UPDATE self_service_validations
+            SET status = $1, tasks = $2, updated_at = CURRENT_TIMESTAMP
             WHERE id = $3
             RETURNING *
         "#
         )
             .bind(status)
             .bind(tasks)
-            .bind(Uuid::from_str(id).unwrap())
+            .bind(id)
+            .fetch_one(pg_pool)
+            .await?
+    )
+}
+
+pub async fn get_self_service_validation(
+    pg_pool: &Pool<Postgres>,
+    id: &str,
+) -> Result<Option<SelfServiceValidation>, QError> {
+    let id = match Uuid::from_str(id) {
+        Ok(id) => id,
+        Err(_) => return Ok(None),
+    };
+
+    Ok(
+        sqlx::query_as::<_, SelfServiceValidation>(
+            r#"
+            SELECT *
+            FROM self_service_validations
+            WHERE id = $1
+        "#
+        )
+            .bind(id)
+            .fetch_optional(pg_pool)
+            .await?
+    )
+}
+
+/// The validation was last updated more than `ttl_in_seconds` ago, compared by the database in its own timezone
+pub async fn is_self_service_validation_expired(
+    pg_pool: &Pool<Postgres>,
+    id: &str,
+    ttl_in_seconds: i64,
+) -> Result<bool, QError> {
+    let id = Uuid::from_str(id).map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+
+    Ok(
+        sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT updated_at <= CURRENT_TIMESTAMP - make_interval(secs => $2)
+            FROM self_service_validations
+            WHERE id = $1
+        "#
+        )
+            .bind(id)
+            .bind(ttl_in_seconds as f64)
             .fetch_one(pg_pool)
             .await?
     )
 }
+
+/// Extend the leases of the unfinished validations of the given owner
+pub async fn renew_self_service_validation_leases(pg_pool: &Pool<Postgres>, owner: &str) -> Result<u64, QError> {
+    Ok(
+        sqlx::query(
+            r#"
+            UPDATE self_service_validations
+            SET lease_until = CURRENT_TIMESTAMP + make_interval(secs => $2)
+            WHERE owner = $1 AND status IN ('QUEUED', 'RUNNING')
+        "#
+        )
+            .bind(owner)
+            .bind(LEASE_DURATION_IN_SECONDS as f64)
+            .execute(pg_pool)
+            .await?
+            .rows_affected()
+    )
+}
+
+/// Take over the unfinished validations whose lease expired, the rows locked by another replica are skipped
+pub async fn claim_expired_self_service_validations(pg_pool: &Pool<Postgres>, owner: &str) -> Result<Vec<SelfServiceValidation>, QError> {
+    Ok(
+        sqlx::query_as::<_, SelfServiceValidation>(
+            r#"
+            UPDATE self_service_validations
+            SET owner = $1, lease_until = CURRENT_TIMESTAMP + make_interval(secs => $2)
+            WHERE id IN (
+                SELECT id
+                FROM self_service_validations
+                WHERE status IN ('QUEUED', 'RUNNING') AND (lease_until IS NULL OR lease_until < CURRENT_TIMESTAMP)
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+        "#
+        )
+            .bind(owner)
+            .bind(LEASE_DURATION_IN_SECONDS as f64)
+            .fetch_all(pg_pool)
+            .await?
+    )
+}
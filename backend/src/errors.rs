@@ -1,10 +1,11 @@
 use std::fmt::Display;
 
-use crate::errors::QError::PostgresError;
+use crate::errors::QError::{MigrationError, PostgresError};
 
 #[derive(Debug)]
 pub enum QError {
     PostgresError(sqlx::Error),
+    MigrationError(String),
 }
 
 impl From<sqlx::Error> for QError {
@@ -17,6 +18,7 @@ impl Display for QError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PostgresError(err) => write!(f, "{}", err),
+            MigrationError(err) => write!(f, "{}", err),
         }
     }
 }
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::{Extension, Json};
+use axum::http::StatusCode;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+
+use crate::config_reload::SharedConfig;
+use crate::constants::{HEALTH_CHECK_TIMEOUT_IN_SECONDS, WORKER_BUSY_GRACE_IN_SECONDS, WORKER_HEARTBEAT_TIMEOUT_IN_SECONDS};
+use crate::migrations::{latest_version, schema_version};
+
+/// Liveness of a worker: it beats while idle, and is stuck when busy with a task for longer than the task can last
+#[derive(Default)]
+pub struct Heartbeat {
+    last_beat_millis: AtomicI64,
+    /// 0 while idle
+    busy_until_millis: AtomicI64,
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        self.last_beat_millis.store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// Flag the worker busy with a task that times out after `max_duration`
+    pub fn set_busy(&self, max_duration: Duration) {
+        let max_duration_millis = i64::try_from(max_duration.as_millis()).unwrap_or(i64::MAX);
+        let busy_until = chrono::Utc::now().timestamp_millis()
+            .saturating_add(max_duration_millis)
+            .saturating_add(WORKER_BUSY_GRACE_IN_SECONDS * 1000);
+
+        self.busy_until_millis.store(busy_until, Ordering::Relaxed);
+        self.beat();
+    }
+
+    pub fn set_idle(&self) {
+        self.busy_until_millis.store(0, Ordering::Relaxed);
+        self.beat();
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let busy_until = self.busy_until_millis.load(Ordering::Relaxed);
+
+        if busy_until != 0 {
+            return match now > busy_until {
+                true => Err(format!("busy {}s longer than the timeout of its task", (now - busy_until) / 1000 + WORKER_BUSY_GRACE_IN_SECONDS)),
+                false => Ok(()),
+            };
+        }
+
+        let since_last_beat = now - self.last_beat_millis.load(Ordering::Relaxed);
+
+        if since_last_beat > WORKER_HEARTBEAT_TIMEOUT_IN_SECONDS * 1000 {
+            return Err(format!("no heartbeat for {}s", since_last_beat / 1000));
+        }
+
+        Ok(())
+    }
+}
+
+/// A worker spawned at startup, it is never restarted
+pub struct Worker {
+    pub name: &'static str,
+    pub handle: JoinHandle<()>,
+    pub heartbeat: Arc<Heartbeat>,
+}
+
+impl Worker {
+    fn check(&self) -> Result<(), String> {
+        if self.handle.is_finished() {
+            return Err("the worker has stopped".to_string());
+        }
+
+        self.heartbeat.check()
+    }
+}
+
+pub struct Workers(pub Vec<Worker>);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Error,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CheckResult {
+    status: CheckStatus,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthReport {
+    status: CheckStatus,
+    checks: BTreeMap<String, CheckResult>,
+}
+
+impl HealthReport {
+    fn new(checks: BTreeMap<String, CheckResult>) -> Self {
+        let status = match checks.values().all(|check| check.status == CheckStatus::Ok) {
+            true => CheckStatus::Ok,
+            false => CheckStatus::Error,
+        };
+
+        HealthReport { status, checks }
+    }
+
+    fn into_response(self) -> (StatusCode, Json<HealthReport>) {
+        match self.status {
+            CheckStatus::Ok => (StatusCode::OK, Json(self)),
+            CheckStatus::Error => (StatusCode::SERVICE_UNAVAILABLE, Json(self)),
+        }
+    }
+}
+
+async fn timed_check<F>(check: F) -> CheckResult where F: Future<Output=Result<(), String>> {
+    let start = Instant::now();
+
+    let result = tokio::time::timeout(Duration::from_secs(HEALTH_CHECK_TIMEOUT_IN_SECONDS), check).await
+        .unwrap_or_else(|_| Err(format!("timed out after {}s", HEALTH_CHECK_TIMEOUT_IN_SECONDS)));
+
+    CheckResult {
+        status: if result.is_ok() { CheckStatus::Ok } else { CheckStatus::Error },
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        message: result.err(),
+    }
+}
+
+async fn check_schema_version(pg_pool: &sqlx::PgPool) -> Result<(), String> {
+    let version = schema_version(pg_pool).await.map_err(|err| err.to_string())?.unwrap_or(0);
+
+    match version.cmp(&latest_version()) {
+        std::cmp::Ordering::Equal => Ok(()),
+        std::cmp::Ordering::Less => Err(format!("the schema is at version {}, {} expected", version, latest_version())),
+        std::cmp::Ordering::Greater => Err(format!("the schema is at version {}, newer than {}", version, latest_version())),
+    }
+}
+
+async fn worker_checks(workers: &Workers, checks: &mut BTreeMap<String, CheckResult>) {
+    for worker in &workers.0 {
+        checks.insert(worker.name.to_string(), timed_check(async { worker.check() }).await);
+    }
+}
+
+/// Ready to serve requests: the database is reachable and migrated, the workers are alive and the config files did not fail to reload
+pub async fn readyz(
+    Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
+    Extension(shared_config): Extension<Arc<SharedConfig>>,
+    Extension(workers): Extension<Arc<Workers>>,
+) -> (StatusCode, Json<HealthReport>) {
+    let mut checks = BTreeMap::new();
+
+    let (database, schema) = tokio::join!(
+        timed_check(async {
+            sqlx::query("SELECT 1").execute(pg_pool.as_ref()).await.map(|_| ()).map_err(|err| err.to_string())
+        }),
+        timed_check(check_schema_version(&pg_pool)),
+    );
+
+    checks.insert("database".to_string(), database);
+    checks.insert("schema".to_string(), schema);
+    checks.insert("config".to_string(), timed_check(async { shared_config.check() }).await);
+
+    worker_checks(&workers, &mut checks).await;
+
+    HealthReport::new(checks).into_response()
+}
+
+/// Alive unless in a state it can't recover from without a restart, i.e. a stopped or stuck worker
+pub async fn livez(Extension(workers): Extension<Arc<Workers>>) -> (StatusCode, Json<HealthReport>) {
+    let mut checks = BTreeMap::new();
+
+    worker_checks(&workers, &mut checks).await;
+
+    HealthReport::new(checks).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+
+    use crate::health::{CheckStatus, Heartbeat, livez, Worker, Workers};
+
+    #[test]
+    fn test_heartbeat() {
+        let heartbeat = Heartbeat::default();
+
+        // never beaten
+        assert!(heartbeat.check().is_err());
+
+        heartbeat.beat();
+        assert!(heartbeat.check().is_ok());
+
+        heartbeat.last_beat_millis.store(0, Ordering::Relaxed);
+        assert!(heartbeat.check().is_err());
+
+        // a busy worker does not beat, it is stuck once its task should have timed out
+        heartbeat.set_busy(Duration::from_secs(60));
+        heartbeat.last_beat_millis.store(0, Ordering::Relaxed);
+        assert!(heartbeat.check().is_ok());
+
+        heartbeat.busy_until_millis.store(chrono::Utc::now().timestamp_millis() - 1000, Ordering::Relaxed);
+        let err = heartbeat.check().unwrap_err();
+        assert!(err.contains("longer than the timeout of its task"), "{}", err);
+
+        heartbeat.set_idle();
+        assert!(heartbeat.check().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_livez() {
+        let heartbeat = Arc::new(Heartbeat::default());
+        heartbeat.beat();
+
+        let running = Worker {
+            name: "running",
+            handle: tokio::spawn(async { tokio::time::sleep(std::time::Duration::from_secs(60)).await }),
+            heartbeat: heartbeat.clone(),
+        };
+
+        let (status, report) = livez(axum::Extension(Arc::new(Workers(vec![running])))).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report.status, CheckStatus::Ok);
+
+        let panicked = Worker {
+            name: "panicked",
+            handle: tokio::spawn(async { panic!("worker panicked") }),
+            heartbeat,
+        };
+
+        while !panicked.handle.is_finished() {
+            tokio::task::yield_now().await;
+        }
+
+        let (status, report) = livez(axum::Extension(Arc::new(Workers(vec![panicked])))).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(report.checks["panicked"].message.as_deref(), Some("the worker has stopped"));
+    }
+}
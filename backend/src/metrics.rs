@@ -0,0 +1,187 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use axum::Extension;
+use axum::extract::{MatchedPath, Request};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use prometheus::{Encoder, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use tokio::sync::mpsc::Sender;
+use tracing::warn;
+
+use crate::database::get_queued_runs_stats;
+use crate::self_service::services::BackgroundWorkerTask;
+
+const TASK_DURATION_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 600.0, 1800.0, 3600.0];
+
+/// Prometheus metrics of the backend, the gauges read from the database and the pool are updated on scrape
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    /// finished runs by final status
+    pub runs: IntCounterVec,
+    pub task_duration: HistogramVec,
+    pub background_worker_busy: IntGauge,
+    pub background_worker_busy_seconds: prometheus::Counter,
+    pub validations_running: IntGauge,
+    pub retention_pruned_runs: IntCounter,
+    pub retention_archived_runs: IntCounter,
+    background_worker_queue_depth: IntGauge,
+    queued_runs: IntGauge,
+    oldest_queued_run_age_seconds: Gauge,
+    db_pool_connections: IntGauge,
+    db_pool_idle_connections: IntGauge,
+    db_pool_max_connections: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("torii".to_string()), None)?;
+
+        let metrics = Metrics {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests handled"),
+                &["method", "route", "status"],
+            )?,
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "duration of the HTTP requests"),
+                &["method", "route", "status"],
+            )?,
+            runs: IntCounterVec::new(
+                Opts::new("runs_total", "runs finished, by final status"),
+                &["section", "action", "status"],
+            )?,
+            task_duration: HistogramVec::new(
+                HistogramOpts::new("task_duration_seconds", "duration of the post_validate tasks")
+                    .buckets(TASK_DURATION_BUCKETS.to_vec()),
+                &["section", "action", "task", "status"],
+            )?,
+            background_worker_busy: IntGauge::new("background_worker_busy", "1 while the background worker executes a run")?,
+            background_worker_busy_seconds: prometheus::Counter::new(
+                "background_worker_busy_seconds_total",
+                "time spent by the background worker executing runs",
+            )?,
+            validations_running: IntGauge::new("validations_running", "validations being executed")?,
+            retention_pruned_runs: IntCounter::new("retention_pruned_runs_total", "runs deleted by the retention job")?,
+            retention_archived_runs: IntCounter::new("retention_archived_runs_total", "runs archived by the retention job")?,
+            background_worker_queue_depth: IntGauge::new(
+                "background_worker_queue_depth",
+                "runs waiting in the queue of the background worker",
+            )?,
+            queued_runs: IntGauge::new("queued_runs", "runs with the QUEUED status")?,
+            oldest_queued_run_age_seconds: Gauge::new(
+                "oldest_queued_run_age_seconds",
+                "age of the oldest run with the QUEUED status, 0 when there is none",
+            )?,
+            db_pool_connections: IntGauge::new("db_pool_connections", "connections opened by the database pool")?,
+            db_pool_idle_connections: IntGauge::new("db_pool_idle_connections", "idle connections of the database pool")?,
+            db_pool_max_connections: IntGauge::new("db_pool_max_connections", "maximum connections of the database pool")?,
+            registry,
+        };
+
+        metrics.registry.register(Box::new(metrics.http_requests.clone()))?;
+        metrics.registry.register(Box::new(metrics.http_request_duration.clone()))?;
+        metrics.registry.register(Box::new(metrics.runs.clone()))?;
+        metrics.registry.register(Box::new(metrics.task_duration.clone()))?;
+        metrics.registry.register(Box::new(metrics.background_worker_busy.clone()))?;
+        metrics.registry.register(Box::new(metrics.background_worker_busy_seconds.clone()))?;
+        metrics.registry.register(Box::new(metrics.validations_running.clone()))?;
+        metrics.registry.register(Box::new(metrics.retention_pruned_runs.clone()))?;
+        metrics.registry.register(Box::new(metrics.retention_archived_runs.clone()))?;
+        metrics.registry.register(Box::new(metrics.background_worker_queue_depth.clone()))?;
+        metrics.registry.register(Box::new(metrics.queued_runs.clone()))?;
+        metrics.registry.register(Box::new(metrics.oldest_queued_run_age_seconds.clone()))?;
+        metrics.registry.register(Box::new(metrics.db_pool_connections.clone()))?;
+        metrics.registry.register(Box::new(metrics.db_pool_idle_connections.clone()))?;
+        metrics.registry.register(Box::new(metrics.db_pool_max_connections.clone()))?;
+
+        Ok(metrics)
+    }
+
+    fn encode(&self) -> Result<String, String> {
+        let mut buffer = vec![];
+
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).map_err(|err| err.to_string())?;
+
+        String::from_utf8(buffer).map_err(|err| err.to_string())
+    }
+}
+
+/// Label of a value serialized as a string, e.g. a status
+pub fn label<T: serde::Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(value)) => value,
+        _ => "unknown".to_string(),
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+    // the names and labels are static, the registration can't fail
+    METRICS.get_or_init(|| Metrics::new().expect("invalid metrics definition"))
+}
+
+/// Middleware counting the requests and their duration by route template, to keep the cardinality low
+pub async fn track_http_requests(matched_path: Option<MatchedPath>, req: Request, next: Next) -> Response {
+    let method = req.method().to_string();
+    let route = matched_path.map(|path| path.as_str().to_string()).unwrap_or_else(|| "unknown".to_string());
+    let start = Instant::now();
+
+    let response = next.run(req).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+
+    metrics().http_requests.with_label_values(&labels).inc();
+    metrics().http_request_duration.with_label_values(&labels).observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+pub async fn get_metrics(
+    Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
+    Extension(tx): Extension<Sender<BackgroundWorkerTask>>,
+) -> Response {
+    let metrics = metrics();
+
+    metrics.background_worker_queue_depth.set((tx.max_capacity() - tx.capacity()) as i64);
+
+    match get_queued_runs_stats(&pg_pool).await {
+        Ok((count, oldest_age_seconds)) => {
+            metrics.queued_runs.set(count);
+            metrics.oldest_queued_run_age_seconds.set(oldest_age_seconds.unwrap_or(0.0));
+        }
+        // the other metrics are still useful when the database is unavailable
+        Err(err) => warn!("failed to get the queued runs metrics: {}", err),
+    }
+
+    metrics.db_pool_connections.set(pg_pool.size() as i64);
+    metrics.db_pool_idle_connections.set(pg_pool.num_idle() as i64);
+    metrics.db_pool_max_connections.set(pg_pool.options().get_max_connections() as i64);
+
+    match metrics.encode() {
+        Ok(body) => ([(CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::metrics;
+
+    #[test]
+    fn test_encode_metrics() {
+        metrics().runs.with_label_values(&["section", "action", "SUCCESS"]).inc();
+        metrics().task_duration.with_label_values(&["section", "action", "deploy", "SUCCESS"]).observe(2.5);
+
+        let body = metrics().encode().unwrap();
+
+        assert!(body.contains(r#"torii_runs_total{action="action",section="section",status="SUCCESS"}"#));
+        assert!(body.contains(r#"torii_task_duration_seconds_bucket{action="action",section="section",status="SUCCESS",task="deploy",le="5"} 1"#));
+        assert!(body.contains("# TYPE torii_db_pool_connections gauge"));
+    }
+}
@@ -0,0 +1,215 @@
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgConnection, Pool, Postgres};
+use tracing::info;
+
+use crate::errors::QError;
+
+/// arbitrary key of the advisory lock held while migrating, so concurrent replicas don't race
+const MIGRATIONS_LOCK_KEY: i64 = 0x74_6f72_6969;
+
+/// A versioned change of the database schema, never edit one once released: add a new one instead
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.sql.as_bytes()))
+    }
+}
+
+/// Migrations applied in order, the versions must be strictly increasing
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create runs",
+        sql: include_str!("../migrations/0001_create_runs.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "add rollback and timeout statuses",
+        sql: include_str!("../migrations/0002_add_rollback_and_timeout_statuses.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "create validations",
+        sql: include_str!("../migrations/0003_create_validations.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "add run listing columns and indexes",
+        sql: include_str!("../migrations/0004_add_run_listing_columns_and_indexes.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "allow run logs of unknown stream",
+        sql: include_str!("../migrations/0005_allow_run_logs_of_unknown_stream.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "add run leases",
+        sql: include_str!("../migrations/0006_add_run_leases.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "add validation leases",
+        sql: include_str!("../migrations/0007_add_validation_leases.sql"),
+    },
+];
+
+const MIGRATIONS_TABLE_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS torii_schema_migrations
+(
+    version     BIGINT PRIMARY KEY                  NOT NULL,
+    description TEXT                                NOT NULL,
+    checksum    VARCHAR(64)                         NOT NULL,
+    applied_at  TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+"#;
+
+/// Apply the pending migrations and return their versions.
+/// Fail if the database has been migrated by a newer version of Torii or if an applied migration has been modified.
+pub async fn migrate(pg_pool: &Pool<Postgres>) -> Result<Vec<i64>, QError> {
+    let mut conn = pg_pool.acquire().await?;
+
+    // session lock: held until released below, whatever the outcome of the migrations
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATIONS_LOCK_KEY)
+        .execute(&mut *conn)
+        .await?;
+
+    let result = apply_pending_migrations(&mut conn).await;
+
+    let unlock = sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATIONS_LOCK_KEY)
+        .execute(&mut *conn)
+        .await;
+
+    if unlock.is_err() {
+        // don't give back a connection still holding the lock to the pool
+        let _ = conn.detach();
+    }
+
+    let applied = result?;
+    unlock?;
+
+    Ok(applied)
+}
+
+/// Latest version of the schema known by this version of Torii
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|migration| migration.version).unwrap_or(0)
+}
+
+/// Version of the schema of the database, None when no migration has been applied
+pub async fn schema_version(pg_pool: &Pool<Postgres>) -> Result<Option<i64>, QError> {
+    let (version, ): (Option<i64>, ) = sqlx::query_as(
+        "SELECT MAX(version) FROM torii_schema_migrations"
+    )
+        .fetch_one(pg_pool)
+        .await?;
+
+    Ok(version)
+}
+
+async fn apply_pending_migrations(conn: &mut PgConnection) -> Result<Vec<i64>, QError> {
+    conn.execute(MIGRATIONS_TABLE_SCHEMA).await?;
+
+    let applied: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT version, checksum FROM torii_schema_migrations ORDER BY version"
+    )
+        .fetch_all(&mut *conn)
+        .await?;
+
+    check_applied_migrations(MIGRATIONS, &applied)?;
+
+    let mut versions = vec![];
+
+    for migration in pending_migrations(MIGRATIONS, &applied) {
+        info!("applying database migration {} ({})", migration.version, migration.description);
+
+        let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+
+        tx.execute(migration.sql).await?;
+
+        sqlx::query("INSERT INTO torii_schema_migrations (version, description, checksum) VALUES ($1, $2, $3)")
+            .bind(migration.version)
+            .bind(migration.description)
+            .bind(migration.checksum())
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        versions.push(migration.version);
+    }
+
+    Ok(versions)
+}
+
+/// Ensure the applied migrations are known to this version of Torii and have not been modified since
+fn check_applied_migrations(migrations: &[Migration], applied: &[(i64, String)]) -> Result<(), QError> {
+    for (version, checksum) in applied {
+        let migration = migrations.iter().find(|migration| migration.version == *version).ok_or_else(|| {
+            QError::MigrationError(format!(
+                "the database schema is at version {} which is newer than the latest migration {} known by this version of Torii",
+                applied.iter().map(|(version, _)| version).max().unwrap_or(version),
+                migrations.last().map(|migration| migration.version).unwrap_or(0),
+            ))
+        })?;
+
+        if migration.checksum() != *checksum {
+            return Err(QError::MigrationError(format!(
+                "migration {} ({}) has been modified after being applied", migration.version, migration.description
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn pending_migrations<'a>(migrations: &'a [Migration], applied: &[(i64, String)]) -> Vec<&'a Migration> {
+    migrations.iter()
+        .filter(|migration| !applied.iter().any(|(version, _)| *version == migration.version))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations::{check_applied_migrations, Migration, MIGRATIONS, pending_migrations};
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration { version: 1, description: "first", sql: "SELECT 1;" },
+        Migration { version: 2, description: "second", sql: "SELECT 2;" },
+    ];
+
+    #[test]
+    fn test_migrations_versions_are_strictly_increasing() {
+        assert!(MIGRATIONS.windows(2).all(|pair| pair[0].version < pair[1].version));
+    }
+
+    #[test]
+    fn test_check_applied_migrations() {
+        let first = TEST_MIGRATIONS[0].checksum();
+
+        assert!(check_applied_migrations(TEST_MIGRATIONS, &[]).is_ok());
+        assert!(check_applied_migrations(TEST_MIGRATIONS, &[(1, first.clone())]).is_ok());
+        // modified after being applied
+        assert!(check_applied_migrations(TEST_MIGRATIONS, &[(1, "bad".to_string())]).is_err());
+        // applied by a newer version of Torii
+        assert!(check_applied_migrations(TEST_MIGRATIONS, &[(1, first), (3, "x".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        let applied = vec![(1, TEST_MIGRATIONS[0].checksum())];
+
+        let pending = pending_migrations(TEST_MIGRATIONS, &applied);
+
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].version, 2);
+        assert_eq!(pending_migrations(TEST_MIGRATIONS, &[]).len(), 2);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::self_service::template::{TemplateContext, validate_path};
+
+/// Condition of the `when` option of a task, e.g. `fields.seed == true && outputs.create-db.created != false`.
+///
+/// Operands are `fields.<slug>` and `outputs.<task id>.<key>` paths, or JSON literals (`true`, `null`, `42`, `'text'`).
+/// An operand alone is true unless it is missing, `null`, `false`, `0` or an empty string.
+/// `!` has the highest precedence, then `==` and `!=`, then `&&`, then `||`.
+#[derive(Debug, PartialEq)]
+pub enum Condition {
+    Or(Box<Condition>, Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    Equal(Operand, Operand),
+    NotEqual(Operand, Operand),
+    Truthy(Operand),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Operand {
+    Path(String),
+    Literal(serde_json::Value),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Operand(Operand),
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+}
+
+impl Condition {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let tokens = tokenize(expression)?;
+        let mut position = 0;
+
+        let condition = parse_or(&tokens, &mut position)
+            .map_err(|err| format!("invalid condition '{}': {}", expression, err))?;
+
+        if position < tokens.len() {
+            return Err(format!("invalid condition '{}': unexpected {:?}", expression, tokens[position]));
+        }
+
+        Ok(condition)
+    }
+
+    /// Check at config load that the condition only references known fields and the tasks it depends on
+    pub fn validate(&self, field_slugs: &[&str], task_ids: &[&str]) -> Result<(), String> {
+        match self {
+            Condition::Or(left, right) | Condition::And(left, right) => {
+                left.validate(field_slugs, task_ids)?;
+                right.validate(field_slugs, task_ids)
+            }
+            Condition::Not(condition) => condition.validate(field_slugs, task_ids),
+            Condition::Equal(left, right) | Condition::NotEqual(left, right) => {
+                left.validate(field_slugs, task_ids)?;
+                right.validate(field_slugs, task_ids)
+            }
+            Condition::Truthy(operand) => operand.validate(field_slugs, task_ids),
+        }
+    }
+
+    pub fn evaluate(&self, template_ctx: &TemplateContext<'_>) -> bool {
+        match self {
+            Condition::Or(left, right) => left.evaluate(template_ctx) || right.evaluate(template_ctx),
+            Condition::And(left, right) => left.evaluate(template_ctx) && right.evaluate(template_ctx),
+            Condition::Not(condition) => !condition.evaluate(template_ctx),
+            Condition::Equal(left, right) => left.value(template_ctx) == right.value(template_ctx),
+            Condition::NotEqual(left, right) => left.value(template_ctx) != right.value(template_ctx),
+            Condition::Truthy(operand) => match operand.value(template_ctx) {
+                serde_json::Value::Null | serde_json::Value::Bool(false) => false,
+                serde_json::Value::Number(number) => number.as_f64() != Some(0.0),
+                serde_json::Value::String(value) => !value.is_empty(),
+                _ => true,
+            },
+        }
+    }
+}
+
+impl Operand {
+    fn validate(&self, field_slugs: &[&str], task_ids: &[&str]) -> Result<(), String> {
+        match self {
+            Operand::Path(path) => validate_path(path, field_slugs, task_ids)
+                .map_err(|err| format!("{} in condition", err)),
+            Operand::Literal(_) => Ok(()),
+        }
+    }
+
+    /// A path that can't be resolved (e.g. output of a skipped task) is `null`
+    fn value(&self, template_ctx: &TemplateContext<'_>) -> serde_json::Value {
+        match self {
+            Operand::Path(path) => template_ctx.lookup(path).cloned().unwrap_or(serde_json::Value::Null),
+            Operand::Literal(value) => value.clone(),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {}
+            '=' | '!' | '&' | '|' => {
+                let token = match (c, chars.peek()) {
+                    ('=', Some('=')) => Token::Equal,
+                    ('!', Some('=')) => Token::NotEqual,
+                    ('&', Some('&')) => Token::And,
+                    ('|', Some('|')) => Token::Or,
+                    ('!', _) => {
+                        tokens.push(Token::Not);
+                        continue;
+                    }
+                    _ => return Err(format!("invalid operator '{}' in condition '{}'", c, expression)),
+                };
+
+                chars.next();
+                tokens.push(token);
+            }
+            '\'' | '"' => {
+                let mut value = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(other) => value.push(other),
+                        None => return Err(format!("unclosed string in condition '{}'", expression)),
+                    }
+                }
+
+                tokens.push(Token::Operand(Operand::Literal(serde_json::Value::String(value))));
+            }
+            _ => {
+                let mut word = c.to_string();
+
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '-' || next == '_' || next == '.') {
+                        break;
+                    }
+
+                    word.push(next);
+                    chars.next();
+                }
+
+                let operand = match word.as_str() {
+                    "true" => Operand::Literal(serde_json::Value::Bool(true)),
+                    "false" => Operand::Literal(serde_json::Value::Bool(false)),
+                    "null" => Operand::Literal(serde_json::Value::Null),
+                    _ if word.starts_with("fields.") || word.starts_with("outputs.") => Operand::Path(word),
+                    _ => match serde_json::from_str::<serde_json::Number>(&word) {
+                        Ok(number) => Operand::Literal(serde_json::Value::Number(number)),
+                        Err(_) => return Err(format!("unknown operand '{}' in condition '{}'", word, expression)),
+                    },
+                };
+
+                tokens.push(Token::Operand(operand));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_or(tokens: &[Token], position: &mut usize) -> Result<Condition, String> {
+    let mut condition = parse_and(tokens, position)?;
+
+    while tokens.get(*position) == Some(&Token::Or) {
+        *position += 1;
+        condition = Condition::Or(Box::new(condition), Box::new(parse_and(tokens, position)?));
+    }
+
+    Ok(condition)
+}
+
+fn parse_and(tokens: &[Token], position: &mut usize) -> Result<Condition, String> {
+    let mut condition = parse_comparison(tokens, position)?;
+
+    while tokens.get(*position) == Some(&Token::And) {
+        *position += 1;
+        condition = Condition::And(Box::new(condition), Box::new(parse_comparison(tokens, position)?));
+    }
+
+    Ok(condition)
+}
+
+fn parse_comparison(tokens: &[Token], position: &mut usize) -> Result<Condition, String> {
+    if tokens.get(*position) == Some(&Token::Not) {
+        *position += 1;
+        return Ok(Condition::Not(Box::new(parse_comparison(tokens, position)?)));
+    }
+
+    let left = parse_operand(tokens, position)?;
+
+    match tokens.get(*position) {
+        Some(Token::Equal) => {
+            *position += 1;
+            Ok(Condition::Equal(left, parse_operand(tokens, position)?))
+        }
+        Some(Token::NotEqual) => {
+            *position += 1;
+            Ok(Condition::NotEqual(left, parse_operand(tokens, position)?))
+        }
+        _ => Ok(Condition::Truthy(left)),
+    }
+}
+
+fn parse_operand(tokens: &[Token], position: &mut usize) -> Result<Operand, String> {
+    match tokens.get(*position) {
+        Some(Token::Operand(Operand::Path(path))) => {
+            *position += 1;
+            Ok(Operand::Path(path.clone()))
+        }
+        Some(Token::Operand(Operand::Literal(value))) => {
+            *position += 1;
+            Ok(Operand::Literal(value.clone()))
+        }
+        Some(token) => Err(format!("expected an operand, got {:?}", token)),
+        None => Err("expected an operand".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use crate::self_service::condition::Condition;
+    use crate::self_service::template::TemplateContext;
+
+    #[test]
+    fn test_evaluate_condition() {
+        let fields = json!({ "seed": true, "region": "eu-west-3", "replicas": 0 });
+        let outputs = BTreeMap::from([("create-db".to_string(), json!({ "created": true }))]);
+        let ctx = TemplateContext { fields: &fields, outputs: &outputs, secret_fields: &[] };
+
+        let evaluate = |expression: &str| Condition::parse(expression).unwrap().evaluate(&ctx);
+
+        assert!(evaluate("fields.seed"));
+        assert!(!evaluate("!fields.seed"));
+        assert!(!evaluate("fields.replicas"));
+        assert!(evaluate("fields.region == 'eu-west-3' && outputs.create-db.created"));
+        assert!(evaluate("fields.region != \"us-east-1\""));
+        assert!(evaluate("fields.replicas == 0 || fields.seed == false"));
+        assert!(!evaluate("outputs.create-db.missing"));
+        assert!(evaluate("outputs.create-db.missing == null"));
+    }
+
+    #[test]
+    fn test_parse_and_validate_condition() {
+        assert!(Condition::parse("fields.seed ==").is_err());
+        assert!(Condition::parse("fields.seed = true").is_err());
+        assert!(Condition::parse("seed == true").is_err());
+        assert!(Condition::parse("fields.seed true").is_err());
+        assert!(Condition::parse("fields.region == 'eu").is_err());
+
+        let condition = Condition::parse("fields.seed && outputs.create-db.created").unwrap();
+        assert!(condition.validate(&["seed"], &["create-db"]).is_ok());
+        assert!(condition.validate(&["seed"], &[]).is_err());
+        assert!(condition.validate(&[], &["create-db"]).is_err());
+    }
+}
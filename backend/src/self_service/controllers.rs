@@ -1,30 +1,36 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{debug_handler, Extension, Json};
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::StatusCode;
-use chrono::{NaiveDateTime, Utc};
 use tokio::sync::mpsc::Sender;
 use tracing::error;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use crate::config_reload::SharedConfig;
+use crate::constants::DRY_RUN_GRACE_IN_SECONDS;
 use crate::database;
-use crate::database::{insert_self_service_run, SelfServiceRunJson, SelfServiceRunLogJson, Status};
-use crate::self_service::{check_json_payload_against_yaml_config_fields, execute_command, ExecValidateScriptRequest, find_self_service_section_by_slug, get_self_service_section_and_action, JobResponse, ResultsResponse};
-use crate::self_service::services::BackgroundWorkerTask;
-use crate::yaml_config::{SelfServiceSectionActionYamlConfig, SelfServiceSectionYamlConfig, YamlConfig};
+use crate::database::{insert_self_service_run, insert_self_service_validation, SelfServiceRunJson, SelfServiceRunLogJson, SelfServiceValidationJson, Status, update_self_service_run, update_self_service_validation};
+use crate::self_service::{check_json_payload_against_yaml_config_fields, ExecValidateScriptRequest, ExecutionContext, find_self_service_section_by_slug, get_self_service_section_and_action, ListRunsQuery, PageResponse, payload_hash, ResultResponse, ResultsResponse, secret_field_slugs, ValidationErrors};
+use crate::self_service::services::{BackgroundWorkerTask, check_validation, execute_run, InstanceId, SelfServiceRunDetailJson, execute_validate_scripts, ValidationScriptPayload, ValidationWorkerTask};
+use crate::yaml_config::{SelfServiceSectionActionYamlConfig, SelfServiceSectionYamlConfig};
 
 #[debug_handler]
 pub async fn list_self_service_sections(
-    Extension(yaml_config): Extension<Arc<YamlConfig>>,
+    Extension(shared_config): Extension<Arc<SharedConfig>>,
 ) -> (StatusCode, Json<ResultsResponse<SelfServiceSectionYamlConfig>>) {
-    (StatusCode::OK, Json(ResultsResponse { message: None, results: yaml_config.self_service.sections.clone() }))
+    (StatusCode::OK, Json(ResultsResponse { message: None, results: shared_config.current().self_service.sections.clone() }))
 }
 
 #[debug_handler]
 pub async fn list_self_service_section_actions(
-    Extension(yaml_config): Extension<Arc<YamlConfig>>,
+    Extension(shared_config): Extension<Arc<SharedConfig>>,
     Path(section_slug): Path<String>,
 ) -> (StatusCode, Json<ResultsResponse<SelfServiceSectionActionYamlConfig>>) {
+    let yaml_config = shared_config.current();
+
     let section = match find_self_service_section_by_slug(&yaml_config.self_service.sections, section_slug.as_str()) {
         Some(section) => section,
         None => return (StatusCode::NOT_FOUND, Json(ResultsResponse {
@@ -40,45 +46,85 @@ pub async fn list_self_service_section_actions(
 pub async fn list_self_service_section_runs_by_section_and_action_slugs(
     Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
     Path((section_slug, action_slug)): Path<(String, String)>,
-) -> (StatusCode, Json<ResultsResponse<SelfServiceRunJson>>) {
-    match database::list_self_service_runs_by_section_and_action_slugs(&pg_pool, &section_slug, &action_slug).await {
-        Ok(action_execution_statuses) => {
-            (StatusCode::OK, Json(ResultsResponse { message: None, results: action_execution_statuses.iter().map(|x| x.to_json()).collect() }))
-        }
-        Err(err) => {
-            error!("failed to list action execution statuses: {:?}", err);
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultsResponse { message: Some(err.to_string()), results: vec![] }))
-        }
-    }
+    Query(query): Query<ListRunsQuery>,
+) -> (StatusCode, Json<PageResponse<SelfServiceRunJson>>) {
+    list_self_service_runs_page(&pg_pool, &query, Some(&section_slug), Some(&action_slug)).await
 }
 
 #[debug_handler]
 pub async fn list_self_service_section_runs_by_section_slug(
     Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
     Path(section_slug): Path<String>,
-) -> (StatusCode, Json<ResultsResponse<SelfServiceRunJson>>) {
-    match database::list_self_service_runs_by_section_slug(&pg_pool, &section_slug).await {
-        Ok(action_execution_statuses) => {
-            (StatusCode::OK, Json(ResultsResponse { message: None, results: action_execution_statuses.iter().map(|x| x.to_json()).collect() }))
+    Query(query): Query<ListRunsQuery>,
+) -> (StatusCode, Json<PageResponse<SelfServiceRunJson>>) {
+    list_self_service_runs_page(&pg_pool, &query, Some(&section_slug), None).await
+}
+
+#[debug_handler]
+pub async fn list_self_service_section_runs(
+    Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
+    Query(query): Query<ListRunsQuery>,
+) -> (StatusCode, Json<PageResponse<SelfServiceRunJson>>) {
+    list_self_service_runs_page(&pg_pool, &query, None, None).await
+}
+
+/// Page of runs, without their input payload and tasks unless they are requested with `include`
+async fn list_self_service_runs_page(
+    pg_pool: &sqlx::PgPool,
+    query: &ListRunsQuery,
+    section_slug: Option<&str>,
+    action_slug: Option<&str>,
+) -> (StatusCode, Json<PageResponse<SelfServiceRunJson>>) {
+    let filters = match query.to_filters(section_slug, action_slug) {
+        Ok(filters) => filters,
+        Err(err) => return (StatusCode::BAD_REQUEST, Json(PageResponse { message: Some(err), results: vec![], next_cursor: None }))
+    };
+
+    match database::list_self_service_runs(pg_pool, &filters).await {
+        Ok(mut self_service_runs) => {
+            let has_next_page = self_service_runs.len() as i64 > filters.limit;
+            self_service_runs.truncate(filters.limit as usize);
+
+            let next_cursor = match has_next_page {
+                true => self_service_runs.last().map(|run| run.cursor()),
+                false => None,
+            };
+
+            (StatusCode::OK, Json(PageResponse {
+                message: None,
+                results: self_service_runs.iter().map(|x| x.to_json()).collect(),
+                next_cursor,
+            }))
         }
         Err(err) => {
             error!("failed to list action execution statuses: {:?}", err);
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultsResponse { message: Some(err.to_string()), results: vec![] }))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(PageResponse { message: Some(err.to_string()), results: vec![], next_cursor: None }))
         }
     }
 }
 
 #[debug_handler]
-pub async fn list_self_service_section_runs(
+pub async fn get_self_service_section_run(
     Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
-) -> (StatusCode, Json<ResultsResponse<SelfServiceRunJson>>) {
-    match database::list_self_service_runs(&pg_pool).await {
-        Ok(self_service_runs) => {
-            (StatusCode::OK, Json(ResultsResponse { message: None, results: self_service_runs.iter().map(|x| x.to_json()).collect() }))
+    Path(run_id): Path<String>,
+) -> (StatusCode, Json<ResultResponse<SelfServiceRunDetailJson>>) {
+    let run = match database::get_self_service_run(&pg_pool, &run_id).await {
+        Ok(Some(run)) => run,
+        Ok(None) => return (StatusCode::NOT_FOUND, Json(ResultResponse {
+            message: Some(format!("Run '{}' not found", run_id)),
+            result: None,
+        })),
+        Err(err) => {
+            error!("failed to get run: {:?}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultResponse { message: Some(err.to_string()), result: None }));
         }
+    };
+
+    match SelfServiceRunDetailJson::new(&run) {
+        Ok(run) => (StatusCode::OK, Json(ResultResponse { message: None, result: Some(run) })),
         Err(err) => {
-            error!("failed to list action execution statuses: {:?}", err);
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultsResponse { message: Some(err.to_string()), results: vec![] }))
+            error!("{}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultResponse { message: Some(err), result: None }))
         }
     }
 }
@@ -86,112 +132,210 @@ pub async fn list_self_service_section_runs(
 #[debug_handler]
 pub async fn list_self_service_section_run_logs(
     Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
-    Path(logs_slug): Path<String>,
+    Path(run_id): Path<String>,
 ) -> (StatusCode, Json<ResultsResponse<SelfServiceRunLogJson>>) {
+    match database::list_self_service_run_logs(&pg_pool, &run_id).await {
+        Ok(logs) => {
+            (StatusCode::OK, Json(ResultsResponse { message: None, results: logs.iter().map(|x| x.to_json()).collect() }))
+        }
+        Err(err) => {
+            error!("failed to list run logs: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultsResponse { message: Some(err.to_string()), results: vec![] }))
+        }
+    }
+}
 
-    // mock data for now
-    let mut data = vec![];
+/// Execute the validate scripts, the errors reported by the first failing script are returned with a message and per field
+#[debug_handler]
+pub async fn exec_self_service_section_action_validate_scripts(
+    Extension(shared_config): Extension<Arc<SharedConfig>>,
+    Path((section_slug, action_slug)): Path<(String, String)>,
+    Json(req): Json<ExecValidateScriptRequest>,
+) -> (StatusCode, Json<ValidationErrors>) {
+    let yaml_config = shared_config.current();
 
-    for i in 1..1000 {
-        data.push(SelfServiceRunLogJson {
-            id: i.to_string(),
-            created_at: "2021-08-01T00:00:00Z".to_string(),
-            is_stderr: i % 2 == 0,
-            message: if i % 2 == 0 { format!("this is an error message {}", i) } else { format!("this is a log message {}", i) },
-        });
+    if let Err(err) = check_json_payload_against_yaml_config_fields(
+        section_slug.as_str(),
+        action_slug.as_str(),
+        &req.payload,
+        &yaml_config,
+    ) {
+        return (StatusCode::BAD_REQUEST, Json(ValidationErrors::new(&err)));
     }
 
-    (StatusCode::OK, Json(ResultsResponse {
-        message: None,
-        results: data,
-    }))
+    let (_, action) = match get_self_service_section_and_action(&yaml_config, section_slug.as_str(), action_slug.as_str()) {
+        Ok((section, action)) => (section, action),
+        Err((status_code, Json(job_response))) => return (status_code, Json(ValidationErrors {
+            message: job_response.message,
+            field_errors: BTreeMap::new(),
+        }))
+    };
+
+    let ctx = ExecutionContext::new(section_slug.as_str(), action_slug.as_str(), req.triggered_by.as_deref());
+
+    let mut scripts = action.validate.iter().flatten()
+        .map(|cmd| ValidationScriptPayload::new(cmd.clone()))
+        .collect::<Vec<_>>();
+
+    execute_validate_scripts(&mut scripts, &req.payload, &secret_field_slugs(action), &ctx, |_| async {}).await;
+
+    if let Some(errors) = scripts.iter().find_map(|script| script.errors()) {
+        return (StatusCode::BAD_REQUEST, Json(errors));
+    }
+
+    (StatusCode::OK, Json(ValidationErrors::default()))
 }
 
+/// Start the validate scripts in the background, the results are available with the id of the returned validation
 #[debug_handler]
-pub async fn exec_self_service_section_action_validate_scripts(
-    Extension(yaml_config): Extension<Arc<YamlConfig>>,
+pub async fn exec_self_service_section_action_validate_scripts_async(
+    Extension(shared_config): Extension<Arc<SharedConfig>>,
+    Extension(tx): Extension<Sender<ValidationWorkerTask>>,
+    Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
+    Extension(instance_id): Extension<InstanceId>,
     Path((section_slug, action_slug)): Path<(String, String)>,
     Json(req): Json<ExecValidateScriptRequest>,
-) -> (StatusCode, Json<JobResponse>) {
-    let _ = match check_json_payload_against_yaml_config_fields(
+) -> (StatusCode, Json<ResultResponse<SelfServiceValidationJson>>) {
+    let yaml_config = shared_config.current();
+
+    if let Err(err) = check_json_payload_against_yaml_config_fields(
         section_slug.as_str(),
         action_slug.as_str(),
         &req.payload,
         &yaml_config,
     ) {
-        Ok(x) => x,
-        Err(err) => return (StatusCode::BAD_REQUEST, Json(JobResponse {
-            message: Some(err),
-        }))
+        return (StatusCode::BAD_REQUEST, Json(ResultResponse { message: Some(err), result: None }));
+    }
+
+    let action = match get_self_service_section_and_action(&yaml_config, section_slug.as_str(), action_slug.as_str()) {
+        Ok((_, action)) => action,
+        Err((status_code, Json(job_response))) => return (status_code, Json(ResultResponse { message: job_response.message, result: None }))
     };
 
-    let (_, action) = match get_self_service_section_and_action(&yaml_config, section_slug.as_str(), action_slug.as_str()) {
-        Ok((section, action)) => (section, action),
-        Err(err) => return err
+    let validation = match insert_self_service_validation(
+        &pg_pool,
+        &section_slug,
+        &action_slug,
+        &payload_hash(&req.payload),
+        &req.payload,
+        &instance_id.0,
+    ).await {
+        Ok(validation) => validation,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultResponse { message: Some(err.to_string()), result: None }))
     };
 
-    for cmd in action.validate.as_ref().unwrap_or(&vec![]) {
-        let _ = match execute_command(cmd, req.payload.to_string().as_str()).await {
-            Ok(_) => (),
-            Err(err) => return (StatusCode::BAD_REQUEST, Json(JobResponse {
-                message: Some(err),
-            }))
-        };
+    if let Err(err) = tx.send(ValidationWorkerTask {
+        validation_id: validation.id(),
+        section_slug: section_slug.clone(),
+        self_service_section_action_yaml_config: action.clone(),
+        req,
+        trace_context: tracing::Span::current().context(),
+    }).await {
+        error!("failed to send validation to validation worker: {}", err);
+
+        let _ = update_self_service_validation(&pg_pool, validation.id().as_str(), Status::Failure, validation.tasks()).await;
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultResponse { message: Some(err.to_string()), result: None }));
     }
 
-    (StatusCode::OK, Json(JobResponse { message: None }))
+    (StatusCode::ACCEPTED, Json(ResultResponse { message: None, result: Some(validation.to_json()) }))
 }
 
+#[debug_handler]
+pub async fn get_self_service_section_action_validation(
+    Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
+    Path(validation_id): Path<String>,
+) -> (StatusCode, Json<ResultResponse<SelfServiceValidationJson>>) {
+    match database::get_self_service_validation(&pg_pool, &validation_id).await {
+        Ok(Some(validation)) => (StatusCode::OK, Json(ResultResponse { message: None, result: Some(validation.to_json()) })),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(ResultResponse {
+            message: Some(format!("Validation '{}' not found", validation_id)),
+            result: None,
+        })),
+        Err(err) => {
+            error!("failed to get validation: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultResponse { message: Some(err.to_string()), result: None }))
+        }
+    }
+}
+
+/// Queue a run of the post_validate tasks. A dry run is executed right away instead of waiting behind the queued runs,
+/// the response contains its tasks and their planned outputs unless it lasts longer than the action allows.
 #[debug_handler]
 pub async fn exec_self_service_section_action_post_validate_scripts(
-    Extension(yaml_config): Extension<Arc<YamlConfig>>,
+    Extension(shared_config): Extension<Arc<SharedConfig>>,
     Extension(tx): Extension<Sender<BackgroundWorkerTask>>,
     Extension(pg_pool): Extension<Arc<sqlx::PgPool>>,
+    Extension(instance_id): Extension<InstanceId>,
     Path((section_slug, action_slug)): Path<(String, String)>,
     Json(req): Json<ExecValidateScriptRequest>,
-) -> (StatusCode, Json<JobResponse>) {
-    let _ = match check_json_payload_against_yaml_config_fields(
+) -> (StatusCode, Json<ResultResponse<SelfServiceRunJson>>) {
+    let (config_version, yaml_config) = shared_config.current_with_version();
+
+    if let Err(err) = check_json_payload_against_yaml_config_fields(
         section_slug.as_str(),
         action_slug.as_str(),
         &req.payload,
         &yaml_config,
     ) {
-        Ok(x) => x,
-        Err(err) => return (StatusCode::BAD_REQUEST, Json(JobResponse {
-            message: Some(err),
-        }))
-    };
+        return (StatusCode::BAD_REQUEST, Json(ResultResponse { message: Some(err), result: None }));
+    }
 
     let service = match get_self_service_section_and_action(&yaml_config, section_slug.as_str(), action_slug.as_str()) {
         Ok((_, service)) => service,
-        Err(err) => return err
+        Err((status_code, Json(job_response))) => return (status_code, Json(ResultResponse { message: job_response.message, result: None }))
     };
 
-    let ces = match insert_self_service_run(
+    // a dry run does not change anything, it does not need a validation
+    if !req.dry_run {
+        if let Err(err) = check_validation(&pg_pool, &section_slug, service, &req).await {
+            return (StatusCode::BAD_REQUEST, Json(ResultResponse { message: Some(err), result: None }));
+        }
+    }
+
+    let run = match insert_self_service_run(
         &pg_pool,
         &section_slug,
         &action_slug,
-        Status::Queued,
         &req.payload,
-        &serde_json::Value::Array(vec![]),
+        req.dry_run,
+        req.triggered_by.as_deref(),
+        &instance_id.0,
     ).await {
-        Ok(ces) => ces,
-        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(JobResponse {
-            message: Some(err.to_string()),
-        }))
+        Ok(run) => run,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultResponse { message: Some(err.to_string()), result: None }))
     };
 
+    let dry_run = req.dry_run;
+    let task = BackgroundWorkerTask::new(run.id(), section_slug.clone(), service.clone(), req)
+        .with_config_version(config_version)
+        .with_trace_context(tracing::Span::current().context());
+
+    if dry_run {
+        // spawned so that the run completes even if the client goes away
+        let dry_run = tokio::spawn(execute_run(task, pg_pool.clone()));
+
+        return match tokio::time::timeout(service.max_run_duration() + Duration::from_secs(DRY_RUN_GRACE_IN_SECONDS), dry_run).await {
+            Ok(Ok(Some(run))) => (StatusCode::OK, Json(ResultResponse { message: Some("dry run executed".to_string()), result: Some(run.to_json()) })),
+            Ok(_) => (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultResponse {
+                message: Some(format!("dry run '{}' did not complete", run.id())),
+                result: None,
+            })),
+            Err(_) => (StatusCode::ACCEPTED, Json(ResultResponse {
+                message: Some(format!("dry run '{}' is still running, its outputs will be available with GET /selfServiceSectionsRuns/{}", run.id(), run.id())),
+                result: Some(run.to_json()),
+            })),
+        };
+    }
+
     // execute post validate scripts
-    let _ = tx.send(BackgroundWorkerTask::new(
-        ces.id(),
-        service.clone(),
-        req,
-    )).await.unwrap_or_else(|err| {
+    if let Err(err) = tx.send(task).await {
         error!("failed to send task to background worker: {}", err);
-        // TODO change catalog execution status to Failure
-    });
 
-    (StatusCode::NO_CONTENT, Json(JobResponse { message: Some("workflow executed".to_string()) }))
+        let _ = update_self_service_run(&pg_pool, run.id().as_str(), Status::Failure, run.tasks()).await;
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(ResultResponse { message: Some(err.to_string()), result: None }));
+    }
+
+    (StatusCode::NO_CONTENT, Json(ResultResponse { message: Some("workflow executed".to_string()), result: None }))
 }
 
 #[cfg(test)]
@@ -202,11 +346,16 @@ mod tests {
     use axum::extract::Path;
     use axum::http::StatusCode;
 
+    use crate::config_reload::SharedConfig;
     use crate::self_service::controllers::exec_self_service_section_action_validate_scripts;
     use crate::self_service::ExecValidateScriptRequest;
     use crate::yaml_config::{SelfServiceSectionActionFieldYamlConfig, SelfServiceSectionActionPostValidateYamlConfig, SelfServiceSectionActionValidateYamlConfig, SelfServiceSectionActionYamlConfig, SelfServiceSectionYamlConfig, SelfServiceYamlConfig, YamlConfig};
     use crate::yaml_config::ActionFieldType::Text;
 
+    fn shared_config(yaml_config: YamlConfig) -> Extension<Arc<SharedConfig>> {
+        Extension(Arc::new(SharedConfig::new(Default::default(), yaml_config, String::new())))
+    }
+
     fn get_yaml_config() -> YamlConfig {
         YamlConfig {
             self_service: SelfServiceYamlConfig {
@@ -222,6 +371,8 @@ mod tests {
                                 description: None,
                                 icon: None,
                                 icon_color: None,
+                                timeout: None,
+                                require_validation: None,
                                 fields: Some(vec![
                                     SelfServiceSectionActionFieldYamlConfig {
                                         slug: "field-1".to_string(),
@@ -232,6 +383,7 @@ mod tests {
                                         default: None,
                                         required: Some(true),
                                         autocomplete_fetcher: None,
+                                        secret: None,
                                     },
                                     SelfServiceSectionActionFieldYamlConfig {
                                         slug: "field-2".to_string(),
@@ -242,6 +394,7 @@ mod tests {
                                         default: None,
                                         required: None,
                                         autocomplete_fetcher: None,
+                                        secret: None,
                                     },
                                 ]),
                                 validate: Some(vec![
@@ -251,38 +404,65 @@ mod tests {
                                             "python3".to_string(),
                                             "examples/validation_script_ok.py".to_string(),
                                         ],
+                                        env: None,
+                                        working_dir: None,
+                                        inherit_env: None,
+                                        input_mode: None,
+                                        execution_policy: None,
+                                        executor: None,
                                     },
                                 ]),
                                 post_validate: Some(vec![
                                     SelfServiceSectionActionPostValidateYamlConfig {
+                                        id: None,
                                         timeout: None,
                                         command: vec![
                                             "python3".to_string(),
                                             "examples/validation_script_ok.py".to_string(),
                                         ],
+                                        http: None,
+                                        depends_on: None,
+                                        when: None,
+                                        continue_on_error: None,
+                                        rollback: None,
+                                        supports_dry_run: None,
+                                        env: None,
+                                        working_dir: None,
+                                        inherit_env: None,
+                                        input_mode: None,
+                                        execution_policy: None,
+                                        executor: None,
                                         output_model: None,
                                     },
                                 ]),
                             },
                         ]),
+                        source_file: None,
                     },
                 ],
-            }
+                retention: None,
+                default_input_mode: None,
+                max_concurrent_validations: None,
+            },
+            include: None,
+            server: None,
+            database: None,
         }
     }
 
     #[tokio::test]
     async fn test_exec_self_service_action_validate_scripts_ok() {
-        let yaml_config = Arc::from(get_yaml_config());
-
         let (status_code, job_response) = exec_self_service_section_action_validate_scripts(
-            Extension(yaml_config),
+            shared_config(get_yaml_config()),
             Path(("section-1".to_string(), "action-1".to_string())),
             Json(ExecValidateScriptRequest {
                 payload: serde_json::json!({
                 "field-1": "value-1",
                 "field-2": "value-2",
-            })
+            }),
+                triggered_by: None,
+                validation_id: None,
+                dry_run: false,
             }),
         ).await;
 
@@ -301,21 +481,69 @@ mod tests {
                 "python3".to_string(),
                 "examples/validation_script_ko.py".to_string(),
             ],
+            env: None,
+            working_dir: None,
+            inherit_env: None,
+            input_mode: None,
+            execution_policy: None,
+            executor: None,
         });
 
         let (status_code, job_response) = exec_self_service_section_action_validate_scripts(
-            Extension(Arc::from(yaml_config)),
+            shared_config(yaml_config),
             Path(("section-1".to_string(), "action-1".to_string())),
             Json(ExecValidateScriptRequest {
                 payload: serde_json::json!({
                 "field-1": "value-1",
                 "field-2": "value-2",
-            })
+            }),
+                triggered_by: None,
+                validation_id: None,
+                dry_run: false,
+            }),
+        ).await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert!(!job_response.message.as_ref().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exec_self_service_action_validate_scripts_field_errors() {
+        let mut yaml_config = get_yaml_config();
+
+        // add a validation script reporting structured errors
+        yaml_config.self_service.sections[0].actions.as_mut().unwrap()[0].validate.as_mut().unwrap().push(SelfServiceSectionActionValidateYamlConfig {
+            timeout: None,
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                r#"echo '{"message": "invalid environment", "field_errors": {"field-1": ["already taken"]}}' >&2; exit 1"#.to_string(),
+            ],
+            env: None,
+            working_dir: None,
+            inherit_env: None,
+            input_mode: None,
+            execution_policy: None,
+            executor: None,
+        });
+
+        let (status_code, validation_errors) = exec_self_service_section_action_validate_scripts(
+            shared_config(yaml_config),
+            Path(("section-1".to_string(), "action-1".to_string())),
+            Json(ExecValidateScriptRequest {
+                payload: serde_json::json!({
+                "field-1": "value-1",
+                "field-2": "value-2",
+            }),
+                triggered_by: None,
+                validation_id: None,
+                dry_run: false,
             }),
         ).await;
 
         assert_eq!(status_code, StatusCode::BAD_REQUEST);
-        assert_eq!(job_response.message.as_ref().unwrap().is_empty(), false);
+        assert_eq!(validation_errors.message, Some("invalid environment".to_string()));
+        assert_eq!(validation_errors.field_errors["field-1"], vec!["already taken".to_string()]);
     }
 
     #[tokio::test]
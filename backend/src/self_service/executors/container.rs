@@ -0,0 +1,679 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{CONTENT_TYPE, HOST};
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::json;
+use tokio::net::UnixStream;
+use tokio::time::timeout;
+use tracing::{debug, error, info};
+
+use crate::constants::DEFAULT_CONTAINER_RUNTIME_SOCKET;
+use crate::self_service::{ExecutionContext, ExecutionError, JobOutputResult};
+use crate::self_service::executors::{Executor, parse_output, write_payload_to_temp_file};
+use crate::self_service::template::percent_encode;
+use crate::yaml_config::{ContainerExecutorYamlConfig, ExternalCommand, InputMode};
+
+const CONTAINER_RUNTIME_API_VERSION: &str = "v1.41";
+const CONTAINER_PAYLOAD_FILE: &str = "/torii/payload.json";
+/// directory of the backend host mounted in the container, the script writes its JSON output there
+const CONTAINER_OUTPUT_DIR: &str = "/torii/output";
+const OUTPUT_FILE_NAME: &str = "output.json";
+const LOGS_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Execute commands in an ephemeral OCI container through the local container runtime
+pub struct ContainerExecutor<'a> {
+    config: &'a ContainerExecutorYamlConfig,
+}
+
+impl<'a> ContainerExecutor<'a> {
+    pub fn new(config: &'a ContainerExecutorYamlConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Executor for ContainerExecutor<'_> {
+    async fn execute<T>(
+        &self,
+        external_command: &T,
+        json_payload: &str,
+        ctx: &ExecutionContext,
+    ) -> Result<JobOutputResult, ExecutionError> where T: ExternalCommand {
+        let cmd_one_line = external_command.one_liner_command();
+
+        debug!("executing script '{}' in container '{}' with payload '{}'", &cmd_one_line, &self.config.image, json_payload);
+
+        let client = ContainerRuntimeClient::new(
+            self.config.runtime_socket.as_deref().unwrap_or(DEFAULT_CONTAINER_RUNTIME_SOCKET)
+        );
+
+        let mut cmd = external_command.get_command().clone();
+
+        // the backend environment is never passed to the container
+        let mut env = vec![];
+
+        for (key, value) in self.config.env.iter().flatten().chain(external_command.get_env().into_iter().flatten()) {
+            env.push(format!("{}={}", key, value));
+        }
+
+        for (key, value) in ctx.env_vars() {
+            env.push(format!("{}={}", key, value));
+        }
+
+        let mut binds = vec![];
+
+        for mount in self.config.mounts.as_ref().unwrap_or(&vec![]) {
+            let source = std::fs::canonicalize(&mount.source)
+                .map_err(|err| format!("Script '{}' failed: invalid mount source '{}': {}", &cmd_one_line, &mount.source, err))?;
+
+            binds.push(bind(&source, &mount.target, mount.read_only.unwrap_or(false)));
+        }
+
+        // each container gets its own output directory, removed once dropped at the end of the execution
+        let output_dir = create_output_dir()
+            .map_err(|err| format!("Script '{}' failed: unable to create output directory: {}", &cmd_one_line, err))?;
+
+        binds.push(bind(output_dir.path(), CONTAINER_OUTPUT_DIR, false));
+        env.push(format!("TORII_OUTPUT_FILE={}/{}", CONTAINER_OUTPUT_DIR, OUTPUT_FILE_NAME));
+
+        // the temporary file is removed once dropped, at the end of the execution
+        let _payload_file = match external_command.get_input_mode() {
+            InputMode::Argv => {
+                cmd.push(json_payload.to_string());
+                None
+            }
+            InputMode::Stdin => {
+                return Err(format!("Script '{}' failed: input_mode 'stdin' is not supported by the container executor", &cmd_one_line).into());
+            }
+            InputMode::File => {
+                let payload_file = write_payload_to_temp_file(json_payload)
+                    .map_err(|err| format!("Script '{}' failed: unable to write payload file: {}", &cmd_one_line, err))?;
+
+                binds.push(bind(payload_file.path(), CONTAINER_PAYLOAD_FILE, true));
+                env.push(format!("TORII_PAYLOAD_FILE={}", CONTAINER_PAYLOAD_FILE));
+                Some(payload_file)
+            }
+            InputMode::Env => {
+                env.push(format!("TORII_PAYLOAD={}", json_payload));
+                None
+            }
+        };
+
+        let mut container_spec = json!({
+            "Image": &self.config.image,
+            "Cmd": cmd,
+            "Env": env,
+            "Labels": {
+                "torii.section": &ctx.section_slug,
+                "torii.action": &ctx.action_slug,
+                "torii.run-id": ctx.run_id.clone().unwrap_or_default(),
+            },
+            "HostConfig": {
+                "Binds": binds,
+            },
+        });
+
+        if let Some(working_dir) = external_command.get_working_dir() {
+            container_spec["WorkingDir"] = json!(working_dir);
+        }
+
+        // start execution timer
+        let start = std::time::Instant::now();
+
+        let container_id = client.create_container(&self.config.image, &container_spec).await
+            .map_err(|err| format!("Script '{}' failed: {}", &cmd_one_line, err))?;
+
+        let result = run_container(&client, &container_id, &cmd_one_line, ctx.timeout(external_command.get_timeout()), ctx).await;
+
+        if let Err(err) = client.remove_container(&container_id).await {
+            error!("failed to remove container '{}': {}", &container_id, err);
+        }
+
+        let exit_code = result?;
+        ctx.set_exit_code(exit_code as i32);
+
+        if exit_code != 0 {
+            return Err(format!("Script '{}' failed: container exited with code {}", &cmd_one_line, exit_code).into());
+        }
+
+        // the output file does not exist when the script did not write any output
+        let output = match std::fs::read_to_string(output_dir.path().join(OUTPUT_FILE_NAME)) {
+            Ok(content) => parse_output(&content).map_err(|err| format!("Script '{}' failed: {}", &cmd_one_line, err))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => json!({}),
+            Err(err) => return Err(format!("Script '{}' failed: unable to read output file: {}", &cmd_one_line, err).into()),
+        };
+
+        Ok(JobOutputResult {
+            one_liner_command: cmd_one_line,
+            output,
+            execution_time_in_millis: start.elapsed().as_millis(),
+        })
+    }
+}
+
+/// Temporary directory for the output file, writable by any user since the user of the image is unknown
+fn create_output_dir() -> std::io::Result<tempfile::TempDir> {
+    let output_dir = tempfile::Builder::new().prefix("torii-output-").tempdir()?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(output_dir.path(), std::fs::Permissions::from_mode(0o777))?;
+    }
+
+    Ok(output_dir)
+}
+
+/// Start the container, forward its logs while it runs and wait for its completion. Return the exit code of the container.
+async fn run_container(
+    client: &ContainerRuntimeClient,
+    container_id: &str,
+    cmd_one_line: &str,
+    task_timeout: Duration,
+    ctx: &ExecutionContext,
+) -> Result<i64, ExecutionError> {
+    client.start_container(container_id).await
+        .map_err(|err| format!("Script '{}' failed: {}", cmd_one_line, err))?;
+
+    // the logs are streamed from the start of the container, the stream ends when the container stops
+    let logs_follower = tokio::spawn(follow_container_logs(
+        client.clone(),
+        container_id.to_string(),
+        cmd_one_line.to_string(),
+        ctx.clone(),
+    ));
+
+    let exit_code = match timeout(task_timeout, client.wait_container(container_id)).await {
+        Ok(exit_code) => exit_code.map_err(|err| format!("Script '{}' failed: {}", cmd_one_line, err))?,
+        Err(_) => return Err(ExecutionError::TimedOut(match client.kill_container(container_id).await {
+            Ok(_) => format!("Script '{}' timed out after {} seconds", cmd_one_line, task_timeout.as_secs()),
+            Err(err) => format!(
+                "Script '{}' timed out after {} seconds, but failed to kill the container: {}",
+                cmd_one_line, task_timeout.as_secs(), err
+            )
+        }))
+    };
+
+    // give the stream some time to flush the last lines
+    let _ = timeout(LOGS_DRAIN_TIMEOUT, logs_follower).await;
+
+    Ok(exit_code)
+}
+
+async fn follow_container_logs(client: ContainerRuntimeClient, container_id: String, cmd_one_line: String, ctx: ExecutionContext) {
+    let mut body = match client.follow_container_logs(&container_id).await {
+        Ok(body) => body,
+        Err(err) => {
+            debug!("failed to follow logs of container '{}': {}", &container_id, err);
+            return;
+        }
+    };
+
+    let mut demultiplexer = LogsDemultiplexer::default();
+
+    let log = |(is_stderr, line): (bool, String)| {
+        info!("[{}] {}", &cmd_one_line, line);
+        ctx.log(Some(is_stderr), &line);
+    };
+
+    loop {
+        match body.frame().await {
+            Some(Ok(frame)) => {
+                if let Ok(chunk) = frame.into_data() {
+                    demultiplexer.push(&chunk).into_iter().for_each(log);
+                }
+            }
+            Some(Err(err)) => {
+                debug!("failed to read logs of container '{}': {}", &container_id, err);
+                break;
+            }
+            None => break,
+        }
+    }
+
+    demultiplexer.finish().into_iter().for_each(log);
+}
+
+fn bind(source: &Path, target: &str, read_only: bool) -> String {
+    match read_only {
+        true => format!("{}:{}:ro", source.display(), target),
+        false => format!("{}:{}", source.display(), target),
+    }
+}
+
+/// Minimal client of the Docker Engine API (also implemented by Podman) over a Unix socket
+#[derive(Clone)]
+pub struct ContainerRuntimeClient {
+    socket_path: PathBuf,
+}
+
+impl ContainerRuntimeClient {
+    pub fn new<P: AsRef<Path>>(socket_path: P) -> Self {
+        Self { socket_path: socket_path.as_ref().to_path_buf() }
+    }
+
+    /// Create the container, pulling the image first if it is not present locally
+    pub async fn create_container(&self, image: &str, container_spec: &serde_json::Value) -> Result<String, String> {
+        let (status, body) = self.request(Method::POST, "/containers/create", Some(container_spec)).await?;
+
+        let (status, body) = match status {
+            StatusCode::NOT_FOUND => {
+                info!("image '{}' not found locally, pulling it", image);
+                self.pull_image(image).await?;
+                self.request(Method::POST, "/containers/create", Some(container_spec)).await?
+            }
+            _ => (status, body),
+        };
+
+        if status != StatusCode::CREATED {
+            return Err(runtime_error("create container", status, &body));
+        }
+
+        serde_json::from_slice::<serde_json::Value>(&body).ok()
+            .and_then(|body| body["Id"].as_str().map(|id| id.to_string()))
+            .ok_or_else(|| "container runtime returned no container id".to_string())
+    }
+
+    pub async fn pull_image(&self, image: &str) -> Result<(), String> {
+        let (from_image, tag) = split_image_reference(image);
+        let path = format!("/images/create?fromImage={}&tag={}", percent_encode(from_image), percent_encode(tag));
+
+        // the progress of the pull is streamed in the body until completion
+        let (status, body) = self.request(Method::POST, path.as_str(), None).await?;
+
+        if status != StatusCode::OK {
+            return Err(runtime_error("pull image", status, &body));
+        }
+
+        // a failed pull (e.g. unknown manifest, denied access) is reported in the stream, the status is still 200
+        match pull_error(&body) {
+            Some(error) => Err(format!("container runtime failed to pull image '{}': {}", image, error)),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn start_container(&self, container_id: &str) -> Result<(), String> {
+        let (status, body) = self.request(Method::POST, format!("/containers/{}/start", container_id).as_str(), None).await?;
+
+        match status {
+            StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED => Ok(()),
+            _ => Err(runtime_error("start container", status, &body)),
+        }
+    }
+
+    /// Block until the container exits and return its exit code
+    pub async fn wait_container(&self, container_id: &str) -> Result<i64, String> {
+        let (status, body) = self.request(Method::POST, format!("/containers/{}/wait", container_id).as_str(), None).await?;
+
+        if status != StatusCode::OK {
+            return Err(runtime_error("wait container", status, &body));
+        }
+
+        serde_json::from_slice::<serde_json::Value>(&body).ok()
+            .and_then(|body| body["StatusCode"].as_i64())
+            .ok_or_else(|| "container runtime returned no exit code".to_string())
+    }
+
+    pub async fn kill_container(&self, container_id: &str) -> Result<(), String> {
+        let (status, body) = self.request(Method::POST, format!("/containers/{}/kill", container_id).as_str(), None).await?;
+
+        match status {
+            StatusCode::NO_CONTENT | StatusCode::CONFLICT => Ok(()), // conflict: the container is not running anymore
+            _ => Err(runtime_error("kill container", status, &body)),
+        }
+    }
+
+    /// Stream of the logs of the container, multiplexed (see `LogsDemultiplexer`), until the container stops
+    pub async fn follow_container_logs(&self, container_id: &str) -> Result<Incoming, String> {
+        let path = format!("/containers/{}/logs?follow=true&stdout=true&stderr=true", container_id);
+        let res = self.send(Method::GET, path.as_str(), None).await?;
+
+        if res.status() != StatusCode::OK {
+            let status = res.status();
+            let body = read_body(res).await?;
+            return Err(runtime_error("follow container logs", status, &body));
+        }
+
+        Ok(res.into_body())
+    }
+
+    pub async fn remove_container(&self, container_id: &str) -> Result<(), String> {
+        let path = format!("/containers/{}?force=true", container_id);
+        let (status, body) = self.request(Method::DELETE, path.as_str(), None).await?;
+
+        match status {
+            StatusCode::NO_CONTENT | StatusCode::NOT_FOUND => Ok(()),
+            _ => Err(runtime_error("remove container", status, &body)),
+        }
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<&serde_json::Value>) -> Result<(StatusCode, Bytes), String> {
+        let res = self.send(method, path, body).await?;
+        let status = res.status();
+
+        Ok((status, read_body(res).await?))
+    }
+
+    async fn send(&self, method: Method, path: &str, body: Option<&serde_json::Value>) -> Result<Response<Incoming>, String> {
+        let stream = UnixStream::connect(&self.socket_path).await
+            .map_err(|err| format!("unable to connect to container runtime socket '{}': {}", self.socket_path.display(), err))?;
+
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await
+            .map_err(|err| format!("container runtime handshake failed: {}", err))?;
+
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                debug!("container runtime connection error: {}", err);
+            }
+        });
+
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("/{}{}", CONTAINER_RUNTIME_API_VERSION, path))
+            .header(HOST, "localhost")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|err| format!("invalid container runtime request: {}", err))?;
+
+        sender.send_request(req).await
+            .map_err(|err| format!("container runtime request failed: {}", err))
+    }
+}
+
+async fn read_body(res: Response<Incoming>) -> Result<Bytes, String> {
+    Ok(res.into_body().collect().await
+        .map_err(|err| format!("unable to read container runtime response: {}", err))?
+        .to_bytes())
+}
+
+fn runtime_error(operation: &str, status: StatusCode, body: &[u8]) -> String {
+    let message = serde_json::from_slice::<serde_json::Value>(body).ok()
+        .and_then(|body| body["message"].as_str().map(|message| message.to_string()))
+        .unwrap_or_else(|| String::from_utf8_lossy(body).to_string());
+
+    format!("container runtime failed to {} ({}): {}", operation, status, message.trim())
+}
+
+/// Error of a pull, from its progress stream: one JSON object per line, e.g. `{"error": "...", "errorDetail": {...}}`
+fn pull_error(body: &[u8]) -> Option<String> {
+    body.split(|byte| *byte == b'\n')
+        .filter_map(|line| serde_json::from_slice::<serde_json::Value>(line).ok())
+        .find_map(|progress| progress["error"].as_str().map(|error| error.to_string()))
+}
+
+/// Split `registry/name:tag` into `registry/name` and `tag` (default: `latest`). Digests are kept as is.
+fn split_image_reference(image: &str) -> (&str, &str) {
+    if image.contains('@') {
+        return (image, "");
+    }
+
+    match image.rsplit_once(':') {
+        // a colon followed by a slash is a registry port, not a tag
+        Some((name, tag)) if !tag.contains('/') => (name, tag),
+        _ => (image, "latest"),
+    }
+}
+
+/// Container logs are multiplexed in frames: 1 byte for the stream (1: stdout, 2: stderr), 3 empty bytes,
+/// 4 bytes for the size (big endian), then the payload. The frames and the lines can be split across the chunks of the stream.
+#[derive(Default)]
+struct LogsDemultiplexer {
+    buffer: Vec<u8>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl LogsDemultiplexer {
+    /// Return the complete lines received, with a flag set for the ones written on stderr
+    fn push(&mut self, chunk: &[u8]) -> Vec<(bool, String)> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut lines = vec![];
+
+        while self.buffer.len() >= 8 {
+            let size = u32::from_be_bytes([self.buffer[4], self.buffer[5], self.buffer[6], self.buffer[7]]) as usize;
+
+            if self.buffer.len() < 8 + size {
+                break;
+            }
+
+            let frame = self.buffer.drain(..8 + size).collect::<Vec<_>>();
+            let is_stderr = frame[0] == 2;
+
+            let stream = match is_stderr {
+                true => &mut self.stderr,
+                false => &mut self.stdout,
+            };
+
+            stream.extend_from_slice(&frame[8..]);
+
+            while let Some(position) = stream.iter().position(|byte| *byte == b'\n') {
+                let line = stream.drain(..=position).collect::<Vec<_>>();
+                lines.push((is_stderr, String::from_utf8_lossy(&line).trim_end().to_string()));
+            }
+        }
+
+        lines
+    }
+
+    /// Return the last lines, not terminated by a new line
+    fn finish(self) -> Vec<(bool, String)> {
+        [(false, self.stdout), (true, self.stderr)].into_iter()
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(is_stderr, line)| (is_stderr, String::from_utf8_lossy(&line).trim_end().to_string()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::{Bytes, Incoming};
+    use hyper::{Request, Response, StatusCode};
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
+    use tokio::net::UnixListener;
+
+    use crate::self_service::ExecutionContext;
+    use crate::self_service::executors::container::{ContainerExecutor, LogsDemultiplexer, pull_error, split_image_reference};
+    use crate::self_service::executors::Executor;
+    use crate::yaml_config::{ContainerExecutorYamlConfig, ExecutorYamlConfig, InputMode, SelfServiceSectionActionValidateYamlConfig};
+
+    type Requests = Arc<Mutex<Vec<(String, String, String)>>>;
+
+    /// Fake container runtime answering the Docker Engine API calls made by the executor
+    fn start_fake_runtime(socket_path: &std::path::Path, exit_code: i64, image_present: bool) -> Requests {
+        let listener = UnixListener::bind(socket_path).unwrap();
+        let requests: Requests = Arc::new(Mutex::new(vec![]));
+        let recorded = requests.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let recorded = recorded.clone();
+
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<Incoming>| {
+                        let recorded = recorded.clone();
+
+                        async move {
+                            let method = req.method().to_string();
+                            let path = req.uri().to_string();
+                            let body = String::from_utf8(req.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+
+                            let pulled = recorded.lock().unwrap().iter().any(|(_, path, _)| path.contains("/images/create"));
+                            recorded.lock().unwrap().push((method.clone(), path.clone(), body.clone()));
+
+                            // the script writes its output in the directory mounted from the host
+                            if path == "/v1.41/containers/create" {
+                                let spec: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+                                let output_dir = spec["HostConfig"]["Binds"].as_array().unwrap().iter()
+                                    .find_map(|bind| bind.as_str().unwrap().strip_suffix(":/torii/output"))
+                                    .unwrap();
+
+                                std::fs::write(format!("{}/output.json", output_dir), r#"{"url": "postgres://db"}"#).unwrap();
+                            }
+
+                            let (status, body) = match (method.as_str(), path.as_str()) {
+                                ("POST", "/v1.41/containers/create") if !image_present && !pulled => {
+                                    (StatusCode::NOT_FOUND, r#"{"message":"No such image"}"#.as_bytes().to_vec())
+                                }
+                                ("POST", "/v1.41/containers/create") => (StatusCode::CREATED, r#"{"Id":"abc"}"#.as_bytes().to_vec()),
+                                ("POST", p) if p.starts_with("/v1.41/images/create") => (StatusCode::OK, vec![]),
+                                ("POST", "/v1.41/containers/abc/start") => (StatusCode::NO_CONTENT, vec![]),
+                                ("POST", "/v1.41/containers/abc/wait") => {
+                                    (StatusCode::OK, format!(r#"{{"StatusCode":{}}}"#, exit_code).into_bytes())
+                                }
+                                ("GET", p) if p.starts_with("/v1.41/containers/abc/logs") => {
+                                    let mut frame = vec![1, 0, 0, 0, 0, 0, 0, 6];
+                                    frame.extend_from_slice(b"hello\n");
+                                    (StatusCode::OK, frame)
+                                }
+                                ("DELETE", "/v1.41/containers/abc?force=true") => (StatusCode::NO_CONTENT, vec![]),
+                                _ => (StatusCode::NOT_FOUND, vec![]),
+                            };
+
+                            Ok::<_, Infallible>(Response::builder().status(status).body(Full::new(Bytes::from(body))).unwrap())
+                        }
+                    });
+
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        requests
+    }
+
+    fn container_command(socket_path: &std::path::Path) -> SelfServiceSectionActionValidateYamlConfig {
+        SelfServiceSectionActionValidateYamlConfig {
+            command: vec!["python".to_string(), "/scripts/validate.py".to_string()],
+            timeout: None,
+            env: None,
+            working_dir: None,
+            inherit_env: None,
+            input_mode: Some(InputMode::Env),
+            execution_policy: None,
+            executor: Some(ExecutorYamlConfig::Container(ContainerExecutorYamlConfig {
+                image: "python:3.12-slim".to_string(),
+                mounts: None,
+                env: None,
+                runtime_socket: Some(socket_path.to_str().unwrap().to_string()),
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_container_executor_ok() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let socket_path = tmp_dir.path().join("runtime.sock");
+        let requests = start_fake_runtime(&socket_path, 0, false);
+
+        let cmd = container_command(&socket_path);
+        let container = match cmd.executor.as_ref().unwrap() {
+            ExecutorYamlConfig::Container(container) => container,
+            _ => unreachable!(),
+        };
+
+        let (log_tx, mut log_rx) = tokio::sync::mpsc::unbounded_channel();
+        let ctx = ExecutionContext::new("section-1", "action-1", None).with_log_sink(log_tx);
+        let result = ContainerExecutor::new(container).execute(&cmd, r#"{"field-1":"value-1"}"#, &ctx).await;
+        assert_eq!(result.unwrap().output, serde_json::json!({"url": "postgres://db"}));
+
+        let line = log_rx.recv().await.unwrap();
+        assert_eq!((line.is_stderr, line.message.as_str()), (Some(false), "hello"));
+
+        let requests = requests.lock().unwrap();
+        let mut calls = requests.iter().map(|(method, path, _)| format!("{} {}", method, path)).collect::<Vec<_>>();
+
+        // the logs are followed while waiting for the container
+        let logs = calls.iter().position(|call| call.starts_with("GET")).unwrap();
+        assert_eq!(calls.remove(logs), "GET /v1.41/containers/abc/logs?follow=true&stdout=true&stderr=true");
+
+        assert_eq!(calls, vec![
+            "POST /v1.41/containers/create",
+            "POST /v1.41/images/create?fromImage=python&tag=3.12-slim",
+            "POST /v1.41/containers/create",
+            "POST /v1.41/containers/abc/start",
+            "POST /v1.41/containers/abc/wait",
+            "DELETE /v1.41/containers/abc?force=true",
+        ]);
+
+        let spec: serde_json::Value = serde_json::from_str(&requests[2].2).unwrap();
+        assert_eq!(spec["Image"], "python:3.12-slim");
+        assert_eq!(spec["Cmd"], serde_json::json!(["python", "/scripts/validate.py"]));
+        let env = spec["Env"].as_array().unwrap();
+        assert!(env.contains(&serde_json::json!("TORII_SECTION=section-1")));
+        assert!(env.contains(&serde_json::json!(r#"TORII_PAYLOAD={"field-1":"value-1"}"#)));
+        assert!(env.contains(&serde_json::json!("TORII_OUTPUT_FILE=/torii/output/output.json")));
+    }
+
+    #[tokio::test]
+    async fn test_container_executor_ko() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let socket_path = tmp_dir.path().join("runtime.sock");
+        let requests = start_fake_runtime(&socket_path, 2, true);
+
+        let cmd = container_command(&socket_path);
+        let container = match cmd.executor.as_ref().unwrap() {
+            ExecutorYamlConfig::Container(container) => container,
+            _ => unreachable!(),
+        };
+
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+        let err = ContainerExecutor::new(container).execute(&cmd, "{}", &ctx).await.unwrap_err();
+        assert!(err.message().contains("container exited with code 2"), "{}", err);
+
+        // the container is removed even if the script failed
+        assert_eq!(requests.lock().unwrap().last().unwrap().0, "DELETE");
+    }
+
+    #[test]
+    fn test_pull_error() {
+        let pulled = b"{\"status\":\"Pulling from library/python\"}\n{\"status\":\"Download complete\"}\n";
+        assert_eq!(pull_error(pulled), None);
+
+        let failed = b"{\"status\":\"Pulling from library/pyton\"}\n\
+            {\"errorDetail\":{\"message\":\"manifest unknown\"},\"error\":\"manifest unknown\"}\n";
+        assert_eq!(pull_error(failed), Some("manifest unknown".to_string()));
+    }
+
+    #[test]
+    fn test_split_image_reference() {
+        assert_eq!(split_image_reference("python"), ("python", "latest"));
+        assert_eq!(split_image_reference("python:3.12"), ("python", "3.12"));
+        assert_eq!(split_image_reference("localhost:5000/tools"), ("localhost:5000/tools", "latest"));
+        assert_eq!(split_image_reference("localhost:5000/tools:1.0"), ("localhost:5000/tools", "1.0"));
+        assert_eq!(split_image_reference("python@sha256:abc"), ("python@sha256:abc", ""));
+    }
+
+    #[test]
+    fn test_demultiplex_logs() {
+        let mut body = vec![1, 0, 0, 0, 0, 0, 0, 4];
+        body.extend_from_slice(b"out\n");
+        body.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 4]);
+        body.extend_from_slice(b"err\n");
+        body.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 4]);
+        body.extend_from_slice(b"last");
+
+        let mut demultiplexer = LogsDemultiplexer::default();
+        assert_eq!(demultiplexer.push(&body), vec![(false, "out".to_string()), (true, "err".to_string())]);
+        assert_eq!(demultiplexer.finish(), vec![(false, "last".to_string())]);
+
+        // the frames are split across the chunks of the stream
+        let mut demultiplexer = LogsDemultiplexer::default();
+        let lines = body.chunks(3).flat_map(|chunk| demultiplexer.push(chunk)).collect::<Vec<_>>();
+        assert_eq!(lines, vec![(false, "out".to_string()), (true, "err".to_string())]);
+    }
+}
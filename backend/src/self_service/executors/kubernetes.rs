@@ -0,0 +1,630 @@
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, Method, StatusCode};
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tracing::{debug, error, info};
+
+use crate::self_service::{ExecutionContext, ExecutionError, JobOutputResult};
+use crate::self_service::executors::{Executor, parse_output};
+use crate::yaml_config::{ExternalCommand, InputMode, KubernetesExecutorYamlConfig};
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+/// the script writes its JSON output there, it is read back from the termination message of the pod
+const TERMINATION_MESSAGE_PATH: &str = "/dev/termination-log";
+/// Kubernetes truncates termination messages to this size, a larger output can not be read back
+const TERMINATION_MESSAGE_MAX_BYTES: usize = 4096;
+const JOB_TTL_SECONDS_AFTER_FINISHED: u64 = 3600;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const LOGS_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Execute commands as Kubernetes Jobs. The Job name is derived from the run and the task index,
+/// so a backend restarted while a Job is running attaches to it instead of creating a new one.
+pub struct KubernetesExecutor<'a> {
+    config: &'a KubernetesExecutorYamlConfig,
+    poll_interval: Duration,
+}
+
+impl<'a> KubernetesExecutor<'a> {
+    pub fn new(config: &'a KubernetesExecutorYamlConfig) -> Self {
+        Self { config, poll_interval: DEFAULT_POLL_INTERVAL }
+    }
+
+    #[cfg(test)]
+    fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+}
+
+struct JobOutcome {
+    succeeded: bool,
+    reason: Option<String>,
+    termination_message: Option<String>,
+    exit_code: Option<i32>,
+}
+
+impl Executor for KubernetesExecutor<'_> {
+    async fn execute<T>(
+        &self,
+        external_command: &T,
+        json_payload: &str,
+        ctx: &ExecutionContext,
+    ) -> Result<JobOutputResult, ExecutionError> where T: ExternalCommand {
+        let cmd_one_line = external_command.one_liner_command();
+
+        debug!("executing script '{}' as a kubernetes job with payload '{}'", &cmd_one_line, json_payload);
+
+        let run_id = ctx.run_id.as_deref()
+            .ok_or_else(|| format!("Script '{}' failed: the kubernetes executor is only available for runs", &cmd_one_line))?;
+
+        let client = KubernetesClient::new(self.config)
+            .map_err(|err| format!("Script '{}' failed: {}", &cmd_one_line, err))?;
+
+        let namespace = self.config.namespace.clone().unwrap_or_else(in_cluster_namespace);
+        let task_timeout = ctx.timeout(external_command.get_timeout());
+        let job_name = job_name(run_id, ctx.task_index);
+
+        let mut command = external_command.get_command().clone();
+        let mut env = vec![];
+
+        for (key, value) in external_command.get_env().into_iter().flatten() {
+            env.push(json!({ "name": key, "value": value }));
+        }
+
+        for (key, value) in ctx.env_vars() {
+            env.push(json!({ "name": key, "value": value }));
+        }
+
+        env.push(json!({ "name": "TORII_OUTPUT_FILE", "value": TERMINATION_MESSAGE_PATH }));
+
+        match external_command.get_input_mode() {
+            InputMode::Argv => command.push(json_payload.to_string()),
+            InputMode::Env => env.push(json!({ "name": "TORII_PAYLOAD", "value": json_payload })),
+            input_mode => return Err(format!(
+                "Script '{}' failed: input_mode '{:?}' is not supported by the kubernetes executor", &cmd_one_line, input_mode
+            ).into()),
+        }
+
+        let labels = json!({
+            "app.kubernetes.io/managed-by": "torii",
+            "torii.io/section": &ctx.section_slug,
+            "torii.io/action": &ctx.action_slug,
+            "torii.io/run-id": run_id,
+        });
+
+        let mut container = json!({
+            "name": "task",
+            "image": &self.config.image,
+            "command": command,
+            "env": env,
+            "terminationMessagePath": TERMINATION_MESSAGE_PATH,
+            "terminationMessagePolicy": "File",
+        });
+
+        if let Some(working_dir) = external_command.get_working_dir() {
+            container["workingDir"] = json!(working_dir);
+        }
+
+        if let Some(resources) = &self.config.resources {
+            container["resources"] = json!({
+                "requests": resources.requests.clone().unwrap_or_default(),
+                "limits": resources.limits.clone().unwrap_or_default(),
+            });
+        }
+
+        let mut pod_spec = json!({
+            "restartPolicy": "Never",
+            "containers": [container],
+        });
+
+        if let Some(service_account) = &self.config.service_account {
+            pod_spec["serviceAccountName"] = json!(service_account);
+        }
+
+        let job = json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": {
+                "name": &job_name,
+                "namespace": &namespace,
+                "labels": labels,
+            },
+            "spec": {
+                "backoffLimit": 0,
+                "activeDeadlineSeconds": task_timeout.as_secs().max(1),
+                "ttlSecondsAfterFinished": JOB_TTL_SECONDS_AFTER_FINISHED,
+                "template": {
+                    "metadata": { "labels": labels },
+                    "spec": pod_spec,
+                },
+            },
+        });
+
+        // start execution timer
+        let start = std::time::Instant::now();
+
+        let created = client.create_job(&namespace, &job).await
+            .map_err(|err| format!("Script '{}' failed: {}", &cmd_one_line, err))?;
+
+        if !created {
+            info!("kubernetes job '{}/{}' already exists, attaching to it", &namespace, &job_name);
+        }
+
+        let wait_for_job = self.wait_for_job(&client, &namespace, &job_name, &cmd_one_line, ctx);
+
+        let outcome = match timeout(task_timeout, wait_for_job).await {
+            Ok(outcome) => outcome.map_err(|err| format!("Script '{}' failed: {}", &cmd_one_line, err))?,
+            Err(_) => return Err(ExecutionError::TimedOut(match client.delete_job(&namespace, &job_name).await {
+                Ok(_) => format!("Script '{}' timed out after {} seconds", &cmd_one_line, task_timeout.as_secs()),
+                Err(err) => format!(
+                    "Script '{}' timed out after {} seconds, but failed to delete the job: {}",
+                    &cmd_one_line, task_timeout.as_secs(), err
+                )
+            }))
+        };
+
+        if let Some(exit_code) = outcome.exit_code {
+            ctx.set_exit_code(exit_code);
+        }
+
+        if !outcome.succeeded {
+            return Err(format!(
+                "Script '{}' failed: kubernetes job '{}/{}' failed: {}",
+                &cmd_one_line, &namespace, &job_name, outcome.reason.unwrap_or_else(|| "unknown reason".to_string())
+            ).into());
+        }
+
+        let output = parse_termination_message(outcome.termination_message.as_deref().unwrap_or_default())
+            .map_err(|err| format!("Script '{}' failed: {}", &cmd_one_line, err))?;
+
+        Ok(JobOutputResult {
+            one_liner_command: cmd_one_line,
+            output,
+            execution_time_in_millis: start.elapsed().as_millis(),
+        })
+    }
+}
+
+impl KubernetesExecutor<'_> {
+    /// Poll the job until it completes, streaming the logs of its pod into the run logs
+    async fn wait_for_job(
+        &self,
+        client: &KubernetesClient,
+        namespace: &str,
+        job_name: &str,
+        cmd_one_line: &str,
+        ctx: &ExecutionContext,
+    ) -> Result<JobOutcome, String> {
+        let mut logs_follower: Option<JoinHandle<()>> = None;
+
+        loop {
+            let job = client.get_job(namespace, job_name).await?;
+            let pod = client.get_job_pod(namespace, job_name).await?;
+
+            if let Some(pod) = &pod {
+                let phase = pod["status"]["phase"].as_str().unwrap_or_default();
+
+                if logs_follower.is_none() && ["Running", "Succeeded", "Failed"].contains(&phase) {
+                    let pod_name = pod["metadata"]["name"].as_str().unwrap_or_default().to_string();
+
+                    logs_follower = Some(tokio::spawn(follow_pod_logs(
+                        client.clone(),
+                        namespace.to_string(),
+                        pod_name,
+                        cmd_one_line.to_string(),
+                        ctx.clone(),
+                    )));
+                }
+            }
+
+            let status = &job["status"];
+
+            let succeeded = if status["succeeded"].as_i64().unwrap_or(0) > 0 {
+                Some(true)
+            } else if status["failed"].as_i64().unwrap_or(0) > 0 || job_condition(status, "Failed").is_some() {
+                Some(false)
+            } else {
+                None
+            };
+
+            if let Some(succeeded) = succeeded {
+                if let Some(logs_follower) = logs_follower {
+                    // the stream ends with the container, give it some time to flush the last lines
+                    let _ = timeout(LOGS_DRAIN_TIMEOUT, logs_follower).await;
+                }
+
+                let terminated = pod.as_ref().map(|pod| pod["status"]["containerStatuses"][0]["state"]["terminated"].clone());
+
+                let termination_message = terminated.as_ref()
+                    .and_then(|terminated| terminated["message"].as_str().map(|message| message.to_string()));
+
+                let exit_code = terminated.as_ref()
+                    .and_then(|terminated| terminated["exitCode"].as_i64())
+                    .map(|exit_code| exit_code as i32);
+
+                let reason = job_condition(status, "Failed")
+                    .or_else(|| exit_code.map(|exit_code| format!("container exited with code {}", exit_code)));
+
+                return Ok(JobOutcome { succeeded, reason, termination_message, exit_code });
+            }
+
+            sleep(self.poll_interval).await;
+        }
+    }
+}
+
+/// Return the message of the condition of the given type if it is true
+fn job_condition(status: &serde_json::Value, condition_type: &str) -> Option<String> {
+    status["conditions"].as_array()?.iter()
+        .find(|condition| condition["type"] == condition_type && condition["status"] == "True")
+        .map(|condition| {
+            let reason = condition["reason"].as_str().unwrap_or_default();
+            let message = condition["message"].as_str().unwrap_or_default();
+            format!("{} {}", reason, message).trim().to_string()
+        })
+}
+
+async fn follow_pod_logs(client: KubernetesClient, namespace: String, pod_name: String, cmd_one_line: String, ctx: ExecutionContext) {
+    let mut response = match client.follow_pod_logs(&namespace, &pod_name).await {
+        Ok(response) => response,
+        Err(err) => {
+            debug!("failed to follow logs of pod '{}/{}': {}", &namespace, &pod_name, err);
+            return;
+        }
+    };
+
+    let mut buffer = Vec::new();
+
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                buffer.extend_from_slice(&chunk);
+
+                while let Some(position) = buffer.iter().position(|byte| *byte == b'\n') {
+                    let line = buffer.drain(..=position).collect::<Vec<_>>();
+                    let line = String::from_utf8_lossy(&line);
+                    info!("[{}] {}", &cmd_one_line, line.trim_end());
+                    ctx.log(None, line.trim_end());
+                }
+            }
+            Ok(None) => break,
+            Err(err) => {
+                debug!("failed to read logs of pod '{}/{}': {}", &namespace, &pod_name, err);
+                break;
+            }
+        }
+    }
+
+    if !buffer.is_empty() {
+        let line = String::from_utf8_lossy(&buffer);
+        info!("[{}] {}", &cmd_one_line, line.trim_end());
+        ctx.log(None, line.trim_end());
+    }
+}
+
+/// JSON output of the script, the termination message is empty when the script did not write any
+fn parse_termination_message(message: &str) -> Result<serde_json::Value, String> {
+    if message.len() >= TERMINATION_MESSAGE_MAX_BYTES {
+        return Err(format!(
+            "output exceeds the {} bytes limit of kubernetes termination messages",
+            TERMINATION_MESSAGE_MAX_BYTES
+        ));
+    }
+
+    parse_output(message)
+}
+
+fn job_name(run_id: &str, task_index: usize) -> String {
+    format!("torii-{}-{}", run_id, task_index).to_lowercase()
+}
+
+fn in_cluster_namespace() -> String {
+    std::fs::read_to_string(Path::new(SERVICE_ACCOUNT_DIR).join("namespace"))
+        .map(|namespace| namespace.trim().to_string())
+        .unwrap_or_else(|_| "default".to_string())
+}
+
+/// Minimal client of the Kubernetes API, authenticated with the service account of the backend when available
+#[derive(Clone)]
+pub struct KubernetesClient {
+    client: Client,
+    api_server: String,
+    token: Option<String>,
+}
+
+impl KubernetesClient {
+    pub fn new(config: &KubernetesExecutorYamlConfig) -> Result<Self, String> {
+        let api_server = match &config.api_server {
+            Some(api_server) => api_server.trim_end_matches('/').to_string(),
+            None => {
+                let host = std::env::var("KUBERNETES_SERVICE_HOST")
+                    .map_err(|_| "no kubernetes api_server configured and KUBERNETES_SERVICE_HOST is not set".to_string())?;
+                let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or("443".to_string());
+                format!("https://{}:{}", host, port)
+            }
+        };
+
+        let mut builder = Client::builder();
+
+        if let Ok(ca) = std::fs::read(Path::new(SERVICE_ACCOUNT_DIR).join("ca.crt")) {
+            let certificate = Certificate::from_pem(&ca)
+                .map_err(|err| format!("invalid kubernetes CA certificate: {}", err))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        // the token is read for each execution as it is rotated by the kubelet
+        let token = std::fs::read_to_string(Path::new(SERVICE_ACCOUNT_DIR).join("token"))
+            .ok()
+            .map(|token| token.trim().to_string());
+
+        let client = builder.build()
+            .map_err(|err| format!("unable to build kubernetes client: {}", err))?;
+
+        Ok(Self { client, api_server, token })
+    }
+
+    /// Create the job, return false if it already exists
+    pub async fn create_job(&self, namespace: &str, job: &serde_json::Value) -> Result<bool, String> {
+        let path = format!("/apis/batch/v1/namespaces/{}/jobs", namespace);
+        let response = self.request(Method::POST, &path).json(job).send().await
+            .map_err(|err| format!("kubernetes request failed: {}", err))?;
+
+        match response.status() {
+            StatusCode::CREATED | StatusCode::OK | StatusCode::ACCEPTED => Ok(true),
+            StatusCode::CONFLICT => Ok(false),
+            status => Err(api_error("create job", status, response).await),
+        }
+    }
+
+    pub async fn get_job(&self, namespace: &str, job_name: &str) -> Result<serde_json::Value, String> {
+        let path = format!("/apis/batch/v1/namespaces/{}/jobs/{}", namespace, job_name);
+        self.get_json(&path, "get job").await
+    }
+
+    /// Return the most recent pod of the job, if any
+    pub async fn get_job_pod(&self, namespace: &str, job_name: &str) -> Result<Option<serde_json::Value>, String> {
+        let path = format!("/api/v1/namespaces/{}/pods?labelSelector=job-name%3D{}", namespace, job_name);
+        let pods = self.get_json(&path, "list pods").await?;
+
+        Ok(pods["items"].as_array()
+            .and_then(|items| items.iter().max_by_key(|pod| pod["metadata"]["creationTimestamp"].as_str().unwrap_or_default().to_string()))
+            .cloned())
+    }
+
+    pub async fn follow_pod_logs(&self, namespace: &str, pod_name: &str) -> Result<reqwest::Response, String> {
+        let path = format!("/api/v1/namespaces/{}/pods/{}/log?follow=true", namespace, pod_name);
+        let response = self.request(Method::GET, &path).send().await
+            .map_err(|err| format!("kubernetes request failed: {}", err))?;
+
+        match response.status() {
+            StatusCode::OK => Ok(response),
+            status => Err(api_error("get pod logs", status, response).await),
+        }
+    }
+
+    pub async fn delete_job(&self, namespace: &str, job_name: &str) -> Result<(), String> {
+        let path = format!("/apis/batch/v1/namespaces/{}/jobs/{}?propagationPolicy=Background", namespace, job_name);
+        let response = self.request(Method::DELETE, &path).send().await
+            .map_err(|err| format!("kubernetes request failed: {}", err))?;
+
+        match response.status() {
+            StatusCode::OK | StatusCode::ACCEPTED | StatusCode::NOT_FOUND => Ok(()),
+            status => {
+                let err = api_error("delete job", status, response).await;
+                error!("{}", &err);
+                Err(err)
+            }
+        }
+    }
+
+    async fn get_json(&self, path: &str, operation: &str) -> Result<serde_json::Value, String> {
+        let response = self.request(Method::GET, path).send().await
+            .map_err(|err| format!("kubernetes request failed: {}", err))?;
+
+        match response.status() {
+            StatusCode::OK => response.json::<serde_json::Value>().await
+                .map_err(|err| format!("invalid kubernetes response: {}", err)),
+            status => Err(api_error(operation, status, response).await),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, format!("{}{}", self.api_server, path));
+
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+async fn api_error(operation: &str, status: StatusCode, response: reqwest::Response) -> String {
+    let body = response.json::<serde_json::Value>().await.unwrap_or_default();
+    let message = body["message"].as_str().unwrap_or_default();
+
+    format!("kubernetes failed to {} ({}): {}", operation, status, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use axum::{Json, Router};
+    use axum::extract::{Path, State};
+    use axum::http::StatusCode;
+    use axum::routing::{get, post};
+    use serde_json::json;
+
+    use crate::self_service::{ExecutionContext, RunLogLine};
+    use crate::self_service::executors::Executor;
+    use crate::self_service::executors::kubernetes::{KubernetesExecutor, parse_termination_message};
+    use crate::yaml_config::{ExecutorYamlConfig, InputMode, KubernetesExecutorYamlConfig, SelfServiceSectionActionPostValidateYamlConfig};
+
+    #[derive(Clone)]
+    struct MockApiServer {
+        job_succeeded: bool,
+        job_polls: Arc<AtomicUsize>,
+        created_jobs: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    async fn create_job(State(state): State<MockApiServer>, Json(job): Json<serde_json::Value>) -> (StatusCode, Json<serde_json::Value>) {
+        state.created_jobs.lock().unwrap().push(job.clone());
+        (StatusCode::CREATED, Json(job))
+    }
+
+    async fn get_job(State(state): State<MockApiServer>, Path((_, name)): Path<(String, String)>) -> Json<serde_json::Value> {
+        // the job is active on the first poll, then completes
+        let status = match (state.job_polls.fetch_add(1, Ordering::SeqCst), state.job_succeeded) {
+            (0, _) => json!({ "active": 1 }),
+            (_, true) => json!({ "succeeded": 1 }),
+            (_, false) => json!({
+                "failed": 1,
+                "conditions": [{ "type": "Failed", "status": "True", "reason": "BackoffLimitExceeded", "message": "Job has reached the specified backoff limit" }]
+            }),
+        };
+
+        Json(json!({ "metadata": { "name": name }, "status": status }))
+    }
+
+    async fn list_pods(State(state): State<MockApiServer>) -> Json<serde_json::Value> {
+        let terminated = match state.job_succeeded {
+            true => json!({ "exitCode": 0, "message": r#"{"url": "postgres://db"}"# }),
+            false => json!({ "exitCode": 3 }),
+        };
+
+        Json(json!({
+            "items": [{
+                "metadata": { "name": "pod-1", "creationTimestamp": "2024-01-01T00:00:00Z" },
+                "status": {
+                    "phase": if state.job_succeeded { "Succeeded" } else { "Failed" },
+                    "containerStatuses": [{ "state": { "terminated": terminated } }]
+                }
+            }]
+        }))
+    }
+
+    async fn pod_logs() -> &'static str {
+        "creating database\ndatabase created\n"
+    }
+
+    async fn start_mock_api_server(job_succeeded: bool) -> (String, MockApiServer) {
+        let state = MockApiServer {
+            job_succeeded,
+            job_polls: Arc::new(AtomicUsize::new(0)),
+            created_jobs: Arc::new(Mutex::new(vec![])),
+        };
+
+        let app = Router::new()
+            .route("/apis/batch/v1/namespaces/:namespace/jobs", post(create_job))
+            .route("/apis/batch/v1/namespaces/:namespace/jobs/:name", get(get_job))
+            .route("/api/v1/namespaces/:namespace/pods", get(list_pods))
+            .route("/api/v1/namespaces/:namespace/pods/:name/log", get(pod_logs))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        (format!("http://{}", addr), state)
+    }
+
+    fn kubernetes_command(api_server: String) -> SelfServiceSectionActionPostValidateYamlConfig {
+        SelfServiceSectionActionPostValidateYamlConfig {
+            id: None,
+            command: vec!["python".to_string(), "/scripts/create_db.py".to_string()],
+            http: None,
+            depends_on: None,
+            when: None,
+            continue_on_error: None,
+            rollback: None,
+            supports_dry_run: None,
+            timeout: Some(60),
+            env: None,
+            working_dir: None,
+            inherit_env: None,
+            input_mode: Some(InputMode::Env),
+            execution_policy: None,
+            executor: Some(ExecutorYamlConfig::Kubernetes(KubernetesExecutorYamlConfig {
+                image: "python:3.12-slim".to_string(),
+                namespace: Some("torii".to_string()),
+                service_account: Some("torii-jobs".to_string()),
+                resources: None,
+                api_server: Some(api_server),
+            })),
+            output_model: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_kubernetes_executor_ok() {
+        let (api_server, state) = start_mock_api_server(true).await;
+        let cmd = kubernetes_command(api_server);
+        let config = match cmd.executor.as_ref().unwrap() {
+            ExecutorYamlConfig::Kubernetes(config) => config,
+            _ => unreachable!(),
+        };
+
+        let (log_tx, mut log_rx) = tokio::sync::mpsc::unbounded_channel::<RunLogLine>();
+        let ctx = ExecutionContext::new("section-1", "action-1", None)
+            .with_run_id("8e0a5b8e-3c1a-4c6e-9d1a-2b7f0b7c9e11")
+            .with_log_sink(log_tx)
+            .with_task_index(1);
+
+        let result = KubernetesExecutor::new(config)
+            .with_poll_interval(Duration::from_millis(10))
+            .execute(&cmd, r#"{"name":"db"}"#, &ctx).await
+            .unwrap();
+
+        assert_eq!(result.output, json!({ "url": "postgres://db" }));
+
+        let job = state.created_jobs.lock().unwrap()[0].clone();
+        assert_eq!(job["metadata"]["name"], "torii-8e0a5b8e-3c1a-4c6e-9d1a-2b7f0b7c9e11-1");
+        assert_eq!(job["spec"]["template"]["spec"]["serviceAccountName"], "torii-jobs");
+        let env = job["spec"]["template"]["spec"]["containers"][0]["env"].as_array().unwrap().clone();
+        assert!(env.contains(&json!({ "name": "TORII_PAYLOAD", "value": r#"{"name":"db"}"# })));
+
+        drop(ctx);
+        let mut logs = vec![];
+        while let Some(line) = log_rx.recv().await {
+            logs.push(line.message);
+        }
+        assert_eq!(logs, vec!["creating database", "database created"]);
+    }
+
+    #[tokio::test]
+    async fn test_kubernetes_executor_ko() {
+        let (api_server, _) = start_mock_api_server(false).await;
+        let cmd = kubernetes_command(api_server);
+        let config = match cmd.executor.as_ref().unwrap() {
+            ExecutorYamlConfig::Kubernetes(config) => config,
+            _ => unreachable!(),
+        };
+
+        let ctx = ExecutionContext::new("section-1", "action-1", None).with_run_id("8e0a5b8e-3c1a-4c6e-9d1a-2b7f0b7c9e11");
+
+        let err = KubernetesExecutor::new(config)
+            .with_poll_interval(Duration::from_millis(10))
+            .execute(&cmd, "{}", &ctx).await
+            .unwrap_err();
+
+        assert!(err.message().contains("BackoffLimitExceeded"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_termination_message() {
+        assert_eq!(parse_termination_message("").unwrap(), json!({}));
+        assert_eq!(parse_termination_message(r#"{"url": "postgres://db"}"#).unwrap(), json!({ "url": "postgres://db" }));
+        assert!(parse_termination_message("not json").unwrap_err().contains("invalid JSON output"));
+
+        let truncated = format!(r#"{{"value": "{}"#, "a".repeat(4096));
+        assert!(parse_termination_message(&truncated[..4096]).unwrap_err().contains("4096 bytes limit"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process;
+use tokio::sync::Notify;
+use tokio::time::timeout;
+use tracing::{debug, info};
+
+use crate::self_service::{ExecutionContext, ExecutionError, JobOutputResult};
+use crate::self_service::executors::{create_output_file, Executor, parse_output, sandbox, write_payload_to_temp_file};
+use crate::yaml_config::{ExternalCommand, InputMode};
+
+/// Execute commands as child processes of the backend
+pub struct LocalProcessExecutor;
+
+impl Executor for LocalProcessExecutor {
+    async fn execute<T>(
+        &self,
+        external_command: &T,
+        json_payload: &str,
+        ctx: &ExecutionContext,
+    ) -> Result<JobOutputResult, ExecutionError> where T: ExternalCommand {
+        let cmd_one_line = external_command.one_liner_command();
+
+        debug!("executing validate script '{}' with payload '{}'", &cmd_one_line, json_payload);
+
+        if external_command.get_command().len() == 1 {
+            return Err(format!("Validate script '{}' is invalid. \
+                    Be explicit on the command to execute, e.g. 'python examples/validation_script.py'",
+                               external_command.get_command()[0]).into());
+        }
+
+        let mut cmd = process::Command::new(&external_command.get_command()[0]);
+
+        for arg in external_command.get_command()[1..].iter() {
+            cmd.arg(arg);
+        }
+
+        if let Some(inherited_env) = ctx.inherited_env() {
+            cmd.env_clear();
+            cmd.envs(inherited_env);
+        }
+
+        if !external_command.inherit_env() {
+            cmd.env_clear();
+
+            // keep PATH to resolve binaries the same way the config validation did
+            if let Some(path) = ctx.inherited_var("PATH") {
+                cmd.env("PATH", path);
+            }
+        }
+
+        if let Some(env) = external_command.get_env() {
+            cmd.envs(env);
+        }
+
+        cmd.envs(ctx.env_vars());
+
+        if let Some(working_dir) = external_command.get_working_dir() {
+            cmd.current_dir(working_dir);
+        }
+
+        // the temporary file is removed once dropped, at the end of the execution
+        let _payload_file = match external_command.get_input_mode() {
+            InputMode::Argv => {
+                cmd.arg(json_payload);
+                None
+            }
+            InputMode::Stdin => {
+                cmd.stdin(Stdio::piped());
+                None
+            }
+            InputMode::File => {
+                let payload_file = write_payload_to_temp_file(json_payload)
+                    .map_err(|err| format!("Validate script '{}' failed: unable to write payload file: {}", &cmd_one_line, err))?;
+
+                cmd.env("TORII_PAYLOAD_FILE", payload_file.path());
+                Some(payload_file)
+            }
+            InputMode::Env => {
+                cmd.env("TORII_PAYLOAD", json_payload);
+                None
+            }
+        };
+
+        let execution_policy = external_command.get_execution_policy().cloned().unwrap_or_default();
+
+        let _tmpdir = sandbox::apply_execution_policy(&mut cmd, &execution_policy)
+            .map_err(|err| format!("Validate script '{}' failed: unable to apply execution policy: {}", &cmd_one_line, err))?;
+
+        // each process gets its own output file, the parallel tasks can't overwrite each other's output
+        let output_file = create_output_file()
+            .and_then(|output_file| sandbox::give_to_process_user(output_file.path(), &execution_policy).map(|_| output_file))
+            .map_err(|err| format!("Validate script '{}' failed: unable to create output file: {}", &cmd_one_line, err))?;
+
+        cmd.env("TORII_OUTPUT_FILE", output_file.path());
+
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        // the processes started by the script are killed with it
+        sandbox::isolate_process_group(&mut cmd);
+
+        // start execution timer
+        let start = std::time::Instant::now();
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(err) => return Err(format!("Validate script '{}' failed: {}", &cmd_one_line, err).into())
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            let payload = json_payload.to_string();
+            let cmd_one_line = cmd_one_line.clone();
+
+            // written in the background to not block on a script which does not read its input (covered by the timeout)
+            tokio::spawn(async move {
+                if let Err(err) = stdin.write_all(payload.as_bytes()).await {
+                    debug!("failed to write payload to the standard input of '{}': {}", &cmd_one_line, err);
+                }
+                // stdin is closed when dropped, signaling the end of the payload
+            });
+        }
+
+        // get stdout and stderr from child and forward it in real time to the logs
+        let output_limit = Arc::new(OutputLimit::new(execution_policy.max_output_bytes));
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(forward_output(stdout, false, cmd_one_line.clone(), output_limit.clone(), ctx.clone()));
+        }
+
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(forward_output(stderr, true, cmd_one_line.clone(), output_limit.clone(), ctx.clone()));
+        }
+
+        let task_timeout = ctx.timeout(external_command.get_timeout());
+
+        let wait_result = timeout(task_timeout, async {
+            tokio::select! {
+                exit_status = child.wait() => Some(exit_status),
+                _ = output_limit.exceeded() => None,
+            }
+        }).await;
+
+        let exit_status = match wait_result {
+            Ok(Some(exit_status)) => exit_status,
+            Ok(None) => return Err(match sandbox::kill_process_tree(&mut child).await {
+                Ok(_) => format!(
+                    "Validate script '{}' exceeded the output limit of {} bytes",
+                    &cmd_one_line,
+                    execution_policy.max_output_bytes.unwrap_or_default()
+                ),
+                Err(err) => format!(
+                    "Validate script '{}' exceeded the output limit of {} bytes, but failed to kill the process: {}",
+                    &cmd_one_line, execution_policy.max_output_bytes.unwrap_or_default(), err
+                )
+            }.into()),
+            Err(_) => return Err(ExecutionError::TimedOut(match sandbox::kill_process_tree(&mut child).await {
+                Ok(_) => format!(
+                    "Validate script '{}' timed out after {} seconds",
+                    &cmd_one_line,
+                    task_timeout.as_secs()
+                ),
+                Err(err) => format!(
+                    "Validate script '{}' timed out after {} seconds, but failed to kill the process: {}",
+                    &cmd_one_line, task_timeout.as_secs(), err
+                )
+            }))
+        }.unwrap();
+
+        // a process killed by a signal has no exit code
+        if let Some(exit_code) = exit_status.code() {
+            ctx.set_exit_code(exit_code);
+        }
+
+        if let Some(reason) = sandbox::describe_limit_exceeded(&exit_status, &execution_policy) {
+            return Err(format!("Validate script '{}' failed: {:?} ({})", &cmd_one_line, exit_status, reason).into());
+        }
+
+        if !exit_status.success() {
+            return Err(format!("Validate script '{}' failed: {:?}", &cmd_one_line, exit_status).into());
+        }
+
+        let output = std::fs::read_to_string(output_file.path())
+            .map_err(|err| format!("Validate script '{}' failed: unable to read output file: {}", &cmd_one_line, err))
+            .and_then(|content| parse_output(&content)
+                .map_err(|err| format!("Validate script '{}' failed: {}", &cmd_one_line, err)))?;
+
+        Ok(JobOutputResult {
+            one_liner_command: cmd_one_line,
+            output,
+            execution_time_in_millis: start.elapsed().as_millis(),
+        })
+    }
+}
+
+/// Number of bytes written by a process on stdout and stderr, compared to an optional limit
+struct OutputLimit {
+    max_bytes: Option<u64>,
+    written_bytes: AtomicU64,
+    notify: Notify,
+}
+
+impl OutputLimit {
+    fn new(max_bytes: Option<u64>) -> Self {
+        Self {
+            max_bytes,
+            written_bytes: AtomicU64::new(0),
+            notify: Notify::new(),
+        }
+    }
+
+    fn add(&self, bytes: usize) {
+        let written_bytes = self.written_bytes.fetch_add(bytes as u64, Ordering::SeqCst) + bytes as u64;
+
+        if self.max_bytes.is_some_and(|max_bytes| written_bytes > max_bytes) {
+            self.notify.notify_one();
+        }
+    }
+
+    /// Resolve once the limit is exceeded, never if there is no limit
+    async fn exceeded(&self) {
+        self.notify.notified().await
+    }
+}
+
+/// Forward each line written by a process to the logs and the run logs until the stream is closed
+async fn forward_output<R>(
+    stream: R,
+    is_stderr: bool,
+    cmd_one_line: String,
+    output_limit: Arc<OutputLimit>,
+    ctx: ExecutionContext,
+) where R: AsyncRead + Unpin {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) => break,
+            Ok(bytes) => {
+                output_limit.add(bytes);
+
+                let line = String::from_utf8_lossy(&line);
+                info!("[{}] {}", &cmd_one_line, line.trim_end());
+                ctx.log(Some(is_stderr), line.trim_end());
+            }
+            Err(err) => {
+                debug!("failed to read output of '{}': {}", &cmd_one_line, err);
+                break;
+            }
+        }
+    }
+}
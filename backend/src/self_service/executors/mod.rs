@@ -0,0 +1,66 @@
+use std::io::Write;
+
+use tempfile::NamedTempFile;
+
+use crate::self_service::{ExecutionContext, ExecutionError, JobOutputResult};
+use crate::yaml_config::ExternalCommand;
+
+pub mod container;
+pub mod kubernetes;
+pub mod local;
+mod sandbox;
+
+/// Run an external command somewhere (local process, container...) and return its output
+pub trait Executor {
+    /// The error is `ExecutionError::TimedOut` when the command was stopped because of its timeout
+    async fn execute<T>(
+        &self,
+        external_command: &T,
+        json_payload: &str,
+        ctx: &ExecutionContext,
+    ) -> Result<JobOutputResult, ExecutionError> where T: ExternalCommand;
+}
+
+/// Temporary file where a command writes its JSON output, its path is passed to the command as TORII_OUTPUT_FILE
+fn create_output_file() -> std::io::Result<NamedTempFile> {
+    tempfile::Builder::new()
+        .prefix("torii-output-")
+        .suffix(".json")
+        .tempfile()
+}
+
+/// JSON output written by a command, an empty object when it did not write any
+fn parse_output(content: &str) -> Result<serde_json::Value, String> {
+    match content.trim() {
+        "" => Ok(serde_json::json!({})),
+        content => serde_json::from_str(content).map_err(|err| format!("invalid JSON output: {}", err)),
+    }
+}
+
+/// Write the payload to a temporary file only readable by the current user
+fn write_payload_to_temp_file(json_payload: &str) -> std::io::Result<NamedTempFile> {
+    let mut payload_file = tempfile::Builder::new()
+        .prefix("torii-payload-")
+        .suffix(".json")
+        .tempfile()?;
+
+    payload_file.write_all(json_payload.as_bytes())?;
+    payload_file.flush()?;
+
+    Ok(payload_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::self_service::executors::parse_output;
+
+    #[test]
+    fn test_parse_output() {
+        assert_eq!(parse_output("").unwrap(), json!({}));
+        assert_eq!(parse_output(" \n").unwrap(), json!({}));
+        assert_eq!(parse_output(r#"{"url": "postgres://db"}"#).unwrap(), json!({"url": "postgres://db"}));
+        assert!(parse_output("not json").is_err());
+    }
+}
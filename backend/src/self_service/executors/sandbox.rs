@@ -0,0 +1,221 @@
+use std::path::Path;
+use std::process::ExitStatus;
+
+use tempfile::TempDir;
+use tokio::process;
+
+use crate::yaml_config::{max_memory_bytes, ExecutionPolicyYamlConfig};
+
+/// Start the command in a new process group, so that the whole process tree can be killed
+pub fn isolate_process_group(cmd: &mut process::Command) {
+    #[cfg(unix)]
+    cmd.process_group(0);
+}
+
+/// Kill the process and all its descendants still in its process group (see `isolate_process_group`)
+pub async fn kill_process_tree(child: &mut process::Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // the id of the process group is the pid of its first process
+        if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } != 0 {
+            let err = std::io::Error::last_os_error();
+
+            if err.raw_os_error() != Some(libc::ESRCH) {
+                return Err(err);
+            }
+        }
+    }
+
+    // reap the process
+    child.kill().await
+}
+
+/// Give a file created by the backend to the user the process runs as (`run_as_uid` and `run_as_gid`), if any
+#[cfg(unix)]
+pub fn give_to_process_user(path: &Path, execution_policy: &ExecutionPolicyYamlConfig) -> std::io::Result<()> {
+    if execution_policy.run_as_uid.is_some() || execution_policy.run_as_gid.is_some() {
+        std::os::unix::fs::chown(path, execution_policy.run_as_uid, execution_policy.run_as_gid)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn give_to_process_user(_path: &Path, _execution_policy: &ExecutionPolicyYamlConfig) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Apply the execution policy to the command before it is spawned.
+/// The returned temporary directory (if `override_tmpdir` is set) must be kept alive until the end of the execution.
+#[cfg(unix)]
+pub fn apply_execution_policy(
+    cmd: &mut process::Command,
+    execution_policy: &ExecutionPolicyYamlConfig,
+) -> std::io::Result<Option<TempDir>> {
+    let tmpdir = if execution_policy.override_tmpdir.unwrap_or(false) {
+        let tmp_dir = tempfile::Builder::new().prefix("torii-tmp-").tempdir()?;
+
+        give_to_process_user(tmp_dir.path(), execution_policy)?;
+
+        for var in ["TMPDIR", "TMP", "TEMP"] {
+            cmd.env(var, tmp_dir.path());
+        }
+
+        Some(tmp_dir)
+    } else {
+        None
+    };
+
+    if let Some(gid) = execution_policy.run_as_gid {
+        cmd.gid(gid);
+    }
+
+    if let Some(uid) = execution_policy.run_as_uid {
+        cmd.uid(uid);
+    }
+
+    let max_memory = match execution_policy.max_memory_mb {
+        Some(max_memory_mb) => Some(max_memory_bytes(max_memory_mb).ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("max_memory_mb {} is too large", max_memory_mb),
+        ))?),
+        None => None,
+    };
+    let max_cpu_time = execution_policy.max_cpu_time_seconds;
+    let max_open_files = execution_policy.max_open_files;
+    let no_new_privileges = execution_policy.no_new_privileges.unwrap_or(false);
+
+    // SAFETY: the closure runs in the forked child before exec, it only calls async-signal-safe functions
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(max_memory) = max_memory {
+                set_rlimit(libc::RLIMIT_AS, max_memory, max_memory)?;
+            }
+
+            if let Some(max_cpu_time) = max_cpu_time {
+                // SIGXCPU is sent at the soft limit, SIGKILL one second later if the process ignores it
+                set_rlimit(libc::RLIMIT_CPU, max_cpu_time, max_cpu_time + 1)?;
+            }
+
+            if let Some(max_open_files) = max_open_files {
+                set_rlimit(libc::RLIMIT_NOFILE, max_open_files, max_open_files)?;
+            }
+
+            #[cfg(target_os = "linux")]
+            if no_new_privileges && libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            #[cfg(not(target_os = "linux"))]
+            let _ = no_new_privileges;
+
+            Ok(())
+        });
+    }
+
+    Ok(tmpdir)
+}
+
+#[cfg(not(unix))]
+pub fn apply_execution_policy(
+    _cmd: &mut process::Command,
+    _execution_policy: &ExecutionPolicyYamlConfig,
+) -> std::io::Result<Option<TempDir>> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "execution_policy is only supported on Unix"))
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type RlimitResource = libc::c_int;
+
+#[cfg(unix)]
+fn set_rlimit(resource: RlimitResource, soft: u64, hard: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+
+    // SAFETY: `limit` is a valid rlimit struct for the duration of the call
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Explain why a process failed when the failure is likely caused by one of the limits of the execution policy
+pub fn describe_limit_exceeded(exit_status: &ExitStatus, execution_policy: &ExecutionPolicyYamlConfig) -> Option<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        let signal = exit_status.signal()?;
+
+        // SIGXCPU is only sent when the soft limit of RLIMIT_CPU is reached
+        if let (Some(max_cpu_time), libc::SIGXCPU) = (execution_policy.max_cpu_time_seconds, signal) {
+            return Some(format!("CPU time limit of {} seconds exceeded", max_cpu_time));
+        }
+
+        // SIGKILL is also sent at the hard limit of RLIMIT_CPU, when the process ignored SIGXCPU
+        if let (Some(max_cpu_time), libc::SIGKILL) = (execution_policy.max_cpu_time_seconds, signal) {
+            return Some(match execution_policy.max_memory_mb {
+                Some(max_memory_mb) => format!(
+                    "CPU time limit of {} seconds or memory limit of {} MB may have been exceeded", max_cpu_time, max_memory_mb,
+                ),
+                None => format!("CPU time limit of {} seconds may have been exceeded", max_cpu_time),
+            });
+        }
+
+        // the kernel does not report allocation failures, a process failing to allocate usually crashes or gets aborted
+        if let (Some(max_memory_mb), libc::SIGKILL | libc::SIGSEGV | libc::SIGABRT) = (execution_policy.max_memory_mb, signal) {
+            return Some(format!("memory limit of {} MB may have been exceeded", max_memory_mb));
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (exit_status, execution_policy);
+
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    use crate::self_service::executors::sandbox::describe_limit_exceeded;
+    use crate::yaml_config::ExecutionPolicyYamlConfig;
+
+    #[test]
+    fn test_describe_limit_exceeded() {
+        let execution_policy = ExecutionPolicyYamlConfig {
+            max_memory_mb: Some(512),
+            max_cpu_time_seconds: Some(10),
+            ..Default::default()
+        };
+
+        let killed_by = |signal: i32| ExitStatus::from_raw(signal);
+        let exited_with = |code: i32| ExitStatus::from_raw(code << 8);
+
+        let reason = describe_limit_exceeded(&killed_by(libc::SIGXCPU), &execution_policy).unwrap();
+        assert!(reason.contains("CPU time limit"), "{}", reason);
+
+        // SIGKILL is sent at the hard limit of RLIMIT_CPU too, either limit may have been hit
+        let reason = describe_limit_exceeded(&killed_by(libc::SIGKILL), &execution_policy).unwrap();
+        assert_eq!(reason, "CPU time limit of 10 seconds or memory limit of 512 MB may have been exceeded");
+
+        let reason = describe_limit_exceeded(&killed_by(libc::SIGSEGV), &execution_policy).unwrap();
+        assert_eq!(reason, "memory limit of 512 MB may have been exceeded");
+
+        let memory_only = ExecutionPolicyYamlConfig { max_cpu_time_seconds: None, ..execution_policy.clone() };
+        let reason = describe_limit_exceeded(&killed_by(libc::SIGKILL), &memory_only).unwrap();
+        assert_eq!(reason, "memory limit of 512 MB may have been exceeded");
+
+        // a regular failure or a termination requested by someone else is not blamed on the limits
+        assert!(describe_limit_exceeded(&exited_with(1), &execution_policy).is_none());
+        assert!(describe_limit_exceeded(&killed_by(libc::SIGTERM), &execution_policy).is_none());
+        assert!(describe_limit_exceeded(&killed_by(libc::SIGKILL), &ExecutionPolicyYamlConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
+use tracing::{debug, info};
+
+use crate::self_service::{ExecutionContext, ExecutionError, JobOutputResult};
+use crate::self_service::template::{percent_encode, TemplateContext};
+use crate::telemetry::current_trace_context;
+use crate::yaml_config::HttpTaskYamlConfig;
+
+/// Send the HTTP request of a task and turn the response into the task output.
+/// The values inserted in the URL are percent-encoded, the body is sent as JSON.
+pub async fn execute_http_task(
+    http: &HttpTaskYamlConfig,
+    timeout: Duration,
+    template_ctx: &TemplateContext<'_>,
+    ctx: &ExecutionContext,
+) -> Result<JobOutputResult, ExecutionError> {
+    let method = Method::from_bytes(http.get_method().as_bytes())
+        .map_err(|err| format!("HTTP task '{}' failed: {}", http.url, err))?;
+
+    let url = template_ctx.render_with(&http.url, percent_encode)
+        .map_err(|err| format!("HTTP task '{} {}' failed: {}", method, http.url, err))?;
+
+    // the values of the secret fields are masked in the logs and the task output
+    let one_liner_command = format!("{} {}", method, template_ctx.render_masked_with(&http.url, percent_encode)?);
+
+    let mut headers = HeaderMap::new();
+
+    for (name, value) in http.headers.as_ref().unwrap_or(&BTreeMap::new()) {
+        let value = template_ctx.render(value)
+            .map_err(|err| format!("HTTP task '{}' failed: {}", &one_liner_command, err))?;
+
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| format!("HTTP task '{}' failed: header '{}': {}", &one_liner_command, name, err))?;
+
+        let value = HeaderValue::from_str(&value)
+            .map_err(|err| format!("HTTP task '{}' failed: header '{}': {}", &one_liner_command, name, err))?;
+
+        headers.insert(name, value);
+    }
+
+    // the called service can continue the trace of the run
+    for (name, value) in current_trace_context() {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+            headers.entry(name).or_insert(value);
+        }
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|err| format!("HTTP task '{}' failed: {}", &one_liner_command, err))?;
+
+    let mut request = client.request(method, url.as_str()).headers(headers);
+
+    if let Some(body) = &http.body {
+        let body = template_ctx.render_json(body)
+            .map_err(|err| format!("HTTP task '{}' failed: {}", &one_liner_command, err))?;
+
+        request = request.json(&body);
+    }
+
+    debug!("executing HTTP task '{}'", &one_liner_command);
+
+    // start execution timer
+    let start = std::time::Instant::now();
+
+    let response = request.send().await.map_err(|err| match err.is_timeout() {
+        true => ExecutionError::TimedOut(format!("HTTP task '{}' timed out after {} seconds", &one_liner_command, timeout.as_secs())),
+        false => ExecutionError::Failed(format!("HTTP task '{}' failed: {}", &one_liner_command, err)),
+    })?;
+
+    let status = response.status();
+
+    let text = response.text().await.map_err(|err| match err.is_timeout() {
+        true => ExecutionError::TimedOut(format!("HTTP task '{}' timed out after {} seconds", &one_liner_command, timeout.as_secs())),
+        false => ExecutionError::Failed(format!("HTTP task '{}' failed: {}", &one_liner_command, err)),
+    })?;
+
+    let execution_time_in_millis = start.elapsed().as_millis();
+
+    info!("HTTP task '{}' returned status {}", &one_liner_command, status.as_u16());
+    ctx.log(Some(false), &format!("{} -> {}", &one_liner_command, status));
+
+    let expected = match &http.expected_status {
+        Some(expected_status) => expected_status.contains(&status.as_u16()),
+        None => status.is_success(),
+    };
+
+    if !expected {
+        ctx.log(Some(true), &text);
+        return Err(format!("HTTP task '{}' failed: unexpected status {}", &one_liner_command, status).into());
+    }
+
+    // a response that is not JSON is kept as a string
+    let body = match text.is_empty() {
+        true => serde_json::Value::Null,
+        false => serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text)),
+    };
+
+    let output = match &http.extract {
+        Some(extract) => {
+            let mut output = serde_json::Map::new();
+
+            for (key, pointer) in extract {
+                let value = body.pointer(pointer).ok_or_else(|| format!(
+                    "HTTP task '{}' failed: '{}' not found in the response for '{}'", &one_liner_command, pointer, key
+                ))?;
+
+                output.insert(key.clone(), value.clone());
+            }
+
+            serde_json::Value::Object(output)
+        }
+        None => serde_json::json!({ "status": status.as_u16(), "body": body }),
+    };
+
+    Ok(JobOutputResult {
+        one_liner_command,
+        output,
+        execution_time_in_millis,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use axum::{Json, Router};
+    use axum::extract::{Path, State};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::{get, post};
+    use serde_json::json;
+
+    use crate::self_service::ExecutionContext;
+    use crate::self_service::http_task::execute_http_task;
+    use crate::self_service::template::TemplateContext;
+    use crate::yaml_config::HttpTaskYamlConfig;
+
+    type Requests = Arc<Mutex<Vec<(String, Option<String>, serde_json::Value)>>>;
+
+    async fn create_environment(
+        State(requests): State<Requests>,
+        Path(team): Path<String>,
+        headers: HeaderMap,
+        Json(body): Json<serde_json::Value>,
+    ) -> (StatusCode, Json<serde_json::Value>) {
+        let authorization = headers.get("authorization").map(|value| value.to_str().unwrap().to_string());
+        requests.lock().unwrap().push((team, authorization, body.clone()));
+
+        (StatusCode::CREATED, Json(json!({ "environment": { "id": 42, "name": body["name"] } })))
+    }
+
+    async fn unavailable() -> (StatusCode, &'static str) {
+        (StatusCode::SERVICE_UNAVAILABLE, "try again later")
+    }
+
+    async fn start_stub_server() -> (String, Requests) {
+        let requests = Requests::default();
+
+        let app = Router::new()
+            .route("/teams/:team/environments", post(create_environment))
+            .route("/unavailable", get(unavailable))
+            .with_state(requests.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        (format!("http://{}", addr), requests)
+    }
+
+    fn http_task(url: String) -> HttpTaskYamlConfig {
+        HttpTaskYamlConfig {
+            method: Some("post".to_string()),
+            url,
+            headers: Some(BTreeMap::from([("Authorization".to_string(), "Bearer {{ outputs.login.token }}".to_string())])),
+            body: Some(json!({ "name": "{{ fields.name }}", "ttl": "{{ fields.ttl }}" })),
+            expected_status: Some(vec![201]),
+            extract: Some(BTreeMap::from([("id".to_string(), "/environment/id".to_string())])),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_http_task_ok() {
+        let (server, requests) = start_stub_server().await;
+        let fields = json!({ "name": "my-env", "ttl": 24, "team": "data eng" });
+        let outputs = BTreeMap::from([("login".to_string(), json!({ "token": "secret" }))]);
+        let template_ctx = TemplateContext { fields: &fields, outputs: &outputs, secret_fields: &[] };
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        let http = http_task(format!("{}/teams/{{{{ fields.team }}}}/environments", server));
+        let result = execute_http_task(&http, Duration::from_secs(10), &template_ctx, &ctx).await.unwrap();
+
+        assert_eq!(result.output, json!({ "id": 42 }));
+        assert_eq!(result.one_liner_command, format!("POST {}/teams/data%20eng/environments", server));
+        assert_eq!(
+            requests.lock().unwrap()[0],
+            ("data eng".to_string(), Some("Bearer secret".to_string()), json!({ "name": "my-env", "ttl": 24 }))
+        );
+
+        // without extract, the whole response is the output
+        let http = HttpTaskYamlConfig { extract: None, ..http };
+        let result = execute_http_task(&http, Duration::from_secs(10), &template_ctx, &ctx).await.unwrap();
+
+        assert_eq!(result.output, json!({ "status": 201, "body": { "environment": { "id": 42, "name": "my-env" } } }));
+    }
+
+    #[tokio::test]
+    async fn test_execute_http_task_ko() {
+        let (server, _) = start_stub_server().await;
+        let fields = json!({});
+        let outputs = BTreeMap::new();
+        let template_ctx = TemplateContext { fields: &fields, outputs: &outputs, secret_fields: &[] };
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        let http = HttpTaskYamlConfig {
+            method: None,
+            url: format!("{}/unavailable", server),
+            headers: None,
+            body: None,
+            expected_status: None,
+            extract: None,
+        };
+
+        let err = execute_http_task(&http, Duration::from_secs(10), &template_ctx, &ctx).await.unwrap_err();
+        assert!(err.message().contains("unexpected status 503"), "{}", err);
+
+        // the output of the previous task is missing
+        let err = execute_http_task(&http_task(format!("{}/teams/a/environments", server)), Duration::from_secs(10), &template_ctx, &ctx).await.unwrap_err();
+        assert!(err.message().contains("outputs.login.token"), "{}", err);
+    }
+}
@@ -1,16 +1,31 @@
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use axum::http::StatusCode;
 use axum::Json;
 use serde::{Deserialize, Serialize};
-use tokio::process;
-use tokio::time::timeout;
-use tracing::debug;
-
-use crate::yaml_config::{ExternalCommand, SelfServiceSectionActionYamlConfig, SelfServiceSectionYamlConfig, YamlConfig};
-
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::Instrument;
+
+use crate::constants::{DEFAULT_RUNS_PAGE_SIZE, MAX_RUNS_PAGE_SIZE};
+use crate::database::{RunFilters, SortOrder, Status};
+use crate::self_service::executors::container::ContainerExecutor;
+use crate::self_service::executors::Executor;
+use crate::self_service::executors::kubernetes::KubernetesExecutor;
+use crate::self_service::executors::local::LocalProcessExecutor;
+use crate::telemetry::trace_env_vars;
+use crate::yaml_config::{ExecutorYamlConfig, ExternalCommand, SelfServiceSectionActionYamlConfig, SelfServiceSectionYamlConfig, YamlConfig};
+
+pub mod condition;
 pub mod controllers;
 pub mod services;
+pub mod template;
+pub mod retention;
+mod executors;
+mod http_task;
 
 #[derive(Serialize, Deserialize)]
 pub struct ResultsResponse<T> {
@@ -18,14 +33,289 @@ pub struct ResultsResponse<T> {
     results: Vec<T>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ResultResponse<T> {
+    pub message: Option<String>,
+    pub result: Option<T>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PageResponse<T> {
+    message: Option<String>,
+    results: Vec<T>,
+    /// cursor to pass to get the next page, None on the last page
+    next_cursor: Option<String>,
+}
+
+/// Query parameters of the run listings
+#[derive(Debug, Default, Deserialize)]
+pub struct ListRunsQuery {
+    /// default: 50, at most 500
+    limit: Option<i64>,
+    cursor: Option<String>,
+    status: Option<Status>,
+    triggered_by: Option<String>,
+    /// e.g. 2024-01-31T00:00:00
+    created_after: Option<chrono::NaiveDateTime>,
+    created_before: Option<chrono::NaiveDateTime>,
+    /// JSON object the input payload must contain, e.g. {"name":"my-env"}
+    payload: Option<String>,
+    sort: Option<SortOrder>,
+    /// comma separated list of the fields omitted by default: input_payload, tasks
+    include: Option<String>,
+}
+
+impl ListRunsQuery {
+    fn to_filters(&self, section_slug: Option<&str>, action_slug: Option<&str>) -> Result<RunFilters, String> {
+        let limit = self.limit.unwrap_or(DEFAULT_RUNS_PAGE_SIZE);
+
+        if !(1..=MAX_RUNS_PAGE_SIZE).contains(&limit) {
+            return Err(format!("limit must be between 1 and {}", MAX_RUNS_PAGE_SIZE));
+        }
+
+        let payload = match &self.payload {
+            Some(payload) => match serde_json::from_str::<serde_json::Value>(payload) {
+                Ok(payload) if payload.is_object() => Some(payload),
+                _ => return Err(format!("payload '{}' must be a JSON object", payload)),
+            },
+            None => None,
+        };
+
+        let include = self.include.as_deref().unwrap_or_default()
+            .split(',')
+            .map(|field| field.trim())
+            .filter(|field| !field.is_empty())
+            .collect::<Vec<_>>();
+
+        if let Some(field) = include.iter().find(|field| !["input_payload", "tasks"].contains(field)) {
+            return Err(format!("include: unknown field '{}', expected input_payload or tasks", field));
+        }
+
+        Ok(RunFilters {
+            section_slug: section_slug.map(|slug| slug.to_string()),
+            action_slug: action_slug.map(|slug| slug.to_string()),
+            status: self.status.clone(),
+            triggered_by: self.triggered_by.clone(),
+            created_after: self.created_after,
+            created_before: self.created_before,
+            payload,
+            cursor: self.cursor.as_deref().map(RunFilters::parse_cursor).transpose()?,
+            sort: self.sort.unwrap_or_default(),
+            limit,
+            include_input_payload: include.contains(&"input_payload"),
+            include_tasks: include.contains(&"tasks"),
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct JobResponse {
     message: Option<String>,
 }
 
+/// Errors of a failed validation, returned by the validate endpoint so the UI can highlight the offending fields.
+/// A validate script reports them by writing a JSON line on stderr (or stdout), e.g.
+/// `{"message": "the environment can't be created", "field_errors": {"name": ["name already taken"]}}`
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct ValidationErrors {
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    field_errors: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    fn new(message: &str) -> Self {
+        Self {
+            message: Some(message.to_string()),
+            field_errors: BTreeMap::new(),
+        }
+    }
+
+    /// Last line written by a script that is a JSON object with a message or field errors, stderr first
+    fn from_output(lines: &[RunLogLine]) -> Option<Self> {
+        let stderr = lines.iter().rev().filter(|line| line.is_stderr == Some(true));
+        let stdout = lines.iter().rev().filter(|line| line.is_stderr != Some(true));
+
+        stderr.chain(stdout).find_map(|line| {
+            serde_json::from_str::<Self>(line.message.trim()).ok()
+                .filter(|errors| errors.message.is_some() || !errors.field_errors.is_empty())
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ExecValidateScriptRequest {
     payload: serde_json::Value,
+    /// identifier of the user triggering the action, exposed to scripts as TORII_USER
+    #[serde(default)]
+    triggered_by: Option<String>,
+    /// id of a successful validation of the same payload, required to execute an action with `require_validation`
+    #[serde(default)]
+    validation_id: Option<String>,
+    /// only the tasks supporting it are executed, with TORII_DRY_RUN=1
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Line written by a command during a run
+#[derive(Debug, Clone)]
+pub struct RunLogLine {
+    /// None when the stream is unknown, e.g. the logs of a Kubernetes pod mix stdout and stderr
+    pub is_stderr: Option<bool>,
+    pub message: String,
+}
+
+/// Context of a command execution, exposed to the child process as TORII_* environment variables
+#[derive(Debug, Clone)]
+pub struct ExecutionContext {
+    pub run_id: Option<String>,
+    pub section_slug: String,
+    pub action_slug: String,
+    pub user: Option<String>,
+    pub task_index: usize,
+    /// where the output of the commands is sent to be stored in the run logs
+    pub log_sink: Option<UnboundedSender<RunLogLine>>,
+    /// end of the action timeout, the timeout of each command is shortened to not exceed it
+    pub deadline: Option<Instant>,
+    /// the commands are told to show what they would do, without doing it
+    pub dry_run: bool,
+    /// exit code of the command, set by the executors when they know it - each task gets its own
+    exit_code: Arc<Mutex<Option<i32>>>,
+    /// environment inherited by the local commands instead of the backend environment, e.g. in the tests
+    inherited_env: Option<Arc<BTreeMap<String, String>>>,
+}
+
+impl ExecutionContext {
+    pub fn new(section_slug: &str, action_slug: &str, user: Option<&str>) -> Self {
+        Self {
+            run_id: None,
+            section_slug: section_slug.to_string(),
+            action_slug: action_slug.to_string(),
+            user: user.map(|user| user.to_string()),
+            task_index: 0,
+            log_sink: None,
+            deadline: None,
+            dry_run: false,
+            exit_code: Arc::default(),
+            inherited_env: None,
+        }
+    }
+
+    pub fn with_run_id(mut self, run_id: &str) -> Self {
+        self.run_id = Some(run_id.to_string());
+        self
+    }
+
+    pub fn with_log_sink(mut self, log_sink: UnboundedSender<RunLogLine>) -> Self {
+        self.log_sink = Some(log_sink);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Environment inherited by the local commands, None for the backend environment
+    pub fn inherited_env(&self) -> Option<&BTreeMap<String, String>> {
+        self.inherited_env.as_deref()
+    }
+
+    /// Value of a variable of the inherited environment
+    pub fn inherited_var(&self, name: &str) -> Option<String> {
+        match &self.inherited_env {
+            Some(inherited_env) => inherited_env.get(name).cloned(),
+            None => std::env::var(name).ok(),
+        }
+    }
+
+    /// Timeout of a command, shortened to the time left before the deadline of the run
+    pub fn timeout(&self, timeout_in_seconds: u64) -> Duration {
+        let timeout = Duration::from_secs(timeout_in_seconds);
+
+        match self.deadline {
+            Some(deadline) => timeout.min(deadline.saturating_duration_since(Instant::now())),
+            None => timeout,
+        }
+    }
+
+    /// Send a line to the run logs, if any
+    pub fn log(&self, is_stderr: Option<bool>, message: &str) {
+        if let Some(log_sink) = &self.log_sink {
+            // the receiver is gone once the run is over, the line can be dropped
+            let _ = log_sink.send(RunLogLine { is_stderr, message: message.to_string() });
+        }
+    }
+
+    pub fn with_task_index(&self, task_index: usize) -> Self {
+        let mut ctx = self.clone();
+        ctx.task_index = task_index;
+        ctx.exit_code = Arc::default();
+        ctx
+    }
+
+    pub fn set_exit_code(&self, exit_code: i32) {
+        *self.exit_code.lock().unwrap() = Some(exit_code);
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        *self.exit_code.lock().unwrap()
+    }
+
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut env_vars = vec![
+            ("TORII_RUN_ID", self.run_id.clone().unwrap_or_default()),
+            ("TORII_SECTION", self.section_slug.clone()),
+            ("TORII_ACTION", self.action_slug.clone()),
+            ("TORII_USER", self.user.clone().unwrap_or_default()),
+            ("TORII_TASK_INDEX", self.task_index.to_string()),
+        ];
+
+        if self.dry_run {
+            env_vars.push(("TORII_DRY_RUN", "1".to_string()));
+        }
+
+        env_vars.extend(trace_env_vars());
+
+        env_vars
+    }
+}
+
+/// Error of a command or of an HTTP request, a timeout is told apart from the other failures
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionError {
+    TimedOut(String),
+    Failed(String),
+}
+
+impl ExecutionError {
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ExecutionError::TimedOut(_))
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ExecutionError::TimedOut(message) | ExecutionError::Failed(message) => message,
+        }
+    }
+}
+
+impl Display for ExecutionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl From<String> for ExecutionError {
+    fn from(message: String) -> Self {
+        ExecutionError::Failed(message)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -35,33 +325,26 @@ pub struct JobOutputResult {
     pub execution_time_in_millis: u128,
 }
 
-fn find_self_service_section_by_slug<'a>(sections: &'a Vec<SelfServiceSectionYamlConfig>, section_slug: &str) -> Option<&'a SelfServiceSectionYamlConfig> {
-    sections.iter().find(|section| section.slug == section_slug)
+/// Hash of a payload, to check that an action is executed with the payload that was validated.
+/// The keys of the JSON objects are sorted, the hash does not depend on their order in the request.
+fn payload_hash(payload: &serde_json::Value) -> String {
+    format!("{:x}", Sha256::digest(payload.to_string().as_bytes()))
 }
 
-fn find_self_service_action_by_slug<'a>(section: &'a SelfServiceSectionYamlConfig, action_slug: &str) -> Option<&'a SelfServiceSectionActionYamlConfig> {
-    section.actions.as_ref().unwrap().iter().find(|action| action.slug == action_slug)
+/// Slugs of the fields whose values must not be shown to the users
+fn secret_field_slugs(action: &SelfServiceSectionActionYamlConfig) -> Vec<String> {
+    action.fields.iter().flatten()
+        .filter(|field| field.secret.unwrap_or(false))
+        .map(|field| field.slug.clone())
+        .collect()
 }
 
-/// Extract the job output from the environment variable TORII_JSON_OUTPUT and reset it to an empty JSON object
-fn consume_job_output_result_from_json_output_env(action_slug: &str, execution_time: u128) -> JobOutputResult {
-    let job_output_result = match std::env::var("TORII_JSON_OUTPUT") {
-        Ok(json_output) => JobOutputResult {
-            one_liner_command: action_slug.to_string(),
-            output: serde_json::from_str(json_output.as_str()).unwrap_or(serde_json::json!({})),
-            execution_time_in_millis: execution_time,
-        },
-        Err(_) => JobOutputResult {
-            one_liner_command: action_slug.to_string(),
-            output: serde_json::json!({}),
-            execution_time_in_millis: execution_time,
-        }
-    };
-
-    // reset the environment variable
-    std::env::set_var("TORII_JSON_OUTPUT", "{}");
+fn find_self_service_section_by_slug<'a>(sections: &'a [SelfServiceSectionYamlConfig], section_slug: &str) -> Option<&'a SelfServiceSectionYamlConfig> {
+    sections.iter().find(|section| section.slug == section_slug)
+}
 
-    job_output_result
+fn find_self_service_action_by_slug<'a>(section: &'a SelfServiceSectionYamlConfig, action_slug: &str) -> Option<&'a SelfServiceSectionActionYamlConfig> {
+    section.actions.as_ref().unwrap().iter().find(|action| action.slug == action_slug)
 }
 
 fn check_json_payload_against_yaml_config_fields(
@@ -102,55 +385,49 @@ fn check_json_payload_against_yaml_config_fields(
 async fn execute_command<T>(
     external_command: &T,
     json_payload: &str,
-) -> Result<JobOutputResult, String> where T: ExternalCommand {
-    let cmd_one_line = external_command.get_command().join(" ");
-
-    debug!("executing validate script '{}' with payload '{}'", &cmd_one_line, json_payload);
-
-    if external_command.get_command().len() == 1 {
-        return Err(format!("Validate script '{}' is invalid. \
-                Be explicit on the command to execute, e.g. 'python examples/validation_script.py'",
-                           external_command.get_command()[0]));
-    }
+    ctx: &ExecutionContext,
+) -> Result<JobOutputResult, ExecutionError> where T: ExternalCommand {
+    let executor = match external_command.get_executor() {
+        None | Some(ExecutorYamlConfig::Local) => "local",
+        Some(ExecutorYamlConfig::Container(_)) => "container",
+        Some(ExecutorYamlConfig::Kubernetes(_)) => "kubernetes",
+    };
 
-    let mut cmd = process::Command::new(&external_command.get_command()[0]);
+    // only the program is recorded, the arguments can contain secrets
+    let program = external_command.get_command().first().cloned().unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "process",
+        otel.name = %format!("process {}", program),
+        torii.executor = executor,
+        process.executable.name = %program,
+        process.exit_code = tracing::field::Empty,
+        otel.status_code = tracing::field::Empty,
+    );
+
+    let result = async {
+        match external_command.get_executor() {
+            None | Some(ExecutorYamlConfig::Local) => {
+                LocalProcessExecutor.execute(external_command, json_payload, ctx).await
+            }
+            Some(ExecutorYamlConfig::Container(container)) => {
+                ContainerExecutor::new(container).execute(external_command, json_payload, ctx).await
+            }
+            Some(ExecutorYamlConfig::Kubernetes(kubernetes)) => {
+                KubernetesExecutor::new(kubernetes).execute(external_command, json_payload, ctx).await
+            }
+        }
+    }.instrument(span.clone()).await;
 
-    for arg in external_command.get_command()[1..].iter() {
-        cmd.arg(arg);
+    if let Some(exit_code) = ctx.exit_code() {
+        span.record("process.exit_code", exit_code);
     }
 
-    cmd.arg(json_payload);
-
-    // start execution timer
-    let start = std::time::Instant::now();
-
-    let mut child = match cmd.spawn() {
-        Ok(child) => child,
-        Err(err) => return Err(format!("Validate script '{}' failed: {}", &cmd_one_line, err))
-    };
-
-    // get stdout and stderr from child and forward it in real time to the upper function
-
-    let exit_status = match timeout(Duration::from_secs(external_command.get_timeout()), child.wait()).await {
-        Ok(exit_status) => exit_status,
-        Err(_) => return Err(match child.kill().await {
-            Ok(_) => format!(
-                "Validate script '{}' timed out after {} seconds",
-                &cmd_one_line,
-                external_command.get_timeout()
-            ),
-            Err(err) => format!(
-                "Validate script '{}' timed out after {} seconds, but failed to kill the process: {}",
-                &cmd_one_line, external_command.get_timeout(), err
-            )
-        })
-    }.unwrap();
-
-    if !exit_status.success() {
-        return Err(format!("Validate script '{}' failed: {:?}", &cmd_one_line, exit_status));
+    if result.is_err() {
+        span.record("otel.status_code", "ERROR");
     }
 
-    Ok(consume_job_output_result_from_json_output_env(cmd_one_line.as_str(), start.elapsed().as_millis()))
+    result
 }
 
 fn get_self_service_section_and_action<'a>(
@@ -178,8 +455,26 @@ fn get_self_service_section_and_action<'a>(
 
 #[cfg(test)]
 mod tests {
-    use crate::self_service::{find_self_service_action_by_slug, find_self_service_section_by_slug};
-    use crate::yaml_config::{SelfServiceSectionActionYamlConfig, SelfServiceSectionYamlConfig};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::database::{SortOrder, Status};
+    use crate::self_service::{execute_command, ExecutionContext, find_self_service_action_by_slug, find_self_service_section_by_slug, ListRunsQuery, payload_hash, RunLogLine, ValidationErrors};
+    use crate::yaml_config::{ExecutionPolicyYamlConfig, InputMode, SelfServiceSectionActionValidateYamlConfig, SelfServiceSectionActionYamlConfig, SelfServiceSectionYamlConfig};
+
+    fn shell_command(script: &str) -> SelfServiceSectionActionValidateYamlConfig {
+        SelfServiceSectionActionValidateYamlConfig {
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            timeout: None,
+            env: None,
+            working_dir: None,
+            inherit_env: None,
+            input_mode: None,
+            execution_policy: None,
+            executor: None,
+        }
+    }
 
     #[test]
     fn test_find_section_by_slug() {
@@ -189,12 +484,14 @@ mod tests {
                 name: "Section 1".to_string(),
                 description: None,
                 actions: None,
+                source_file: None,
             },
             SelfServiceSectionYamlConfig {
                 slug: "section-2".to_string(),
                 name: "Section 2".to_string(),
                 description: None,
                 actions: None,
+                source_file: None,
             },
         ];
 
@@ -216,6 +513,8 @@ mod tests {
                     description: None,
                     icon: None,
                     icon_color: None,
+                    timeout: None,
+                    require_validation: None,
                     fields: None,
                     validate: None,
                     post_validate: None,
@@ -226,15 +525,240 @@ mod tests {
                     description: None,
                     icon: None,
                     icon_color: None,
+                    timeout: None,
+                    require_validation: None,
                     fields: None,
                     validate: None,
                     post_validate: None,
                 },
             ]),
+            source_file: None,
         };
 
         assert_eq!(find_self_service_action_by_slug(&section, "action-1"), Some(&section.actions.as_ref().unwrap()[0]));
         assert_eq!(find_self_service_action_by_slug(&section, "action-2"), Some(&section.actions.as_ref().unwrap()[1]));
         assert_eq!(find_self_service_action_by_slug(&section, "action-3"), None);
     }
+
+    /// Environment of the backend seen by the commands, the process environment is not modified by the tests
+    fn test_env(name: &str, value: &str) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("PATH".to_string(), std::env::var("PATH").unwrap()),
+            (name.to_string(), value.to_string()),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_with_env_and_context() {
+        let mut cmd = shell_command(r#"
+            test "$MY_VAR" = "my-value" &&
+            test "$TORII_TEST_INHERITED" = "inherited" &&
+            test "$TORII_RUN_ID" = "run-1" &&
+            test "$TORII_SECTION" = "section-1" &&
+            test "$TORII_ACTION" = "action-1" &&
+            test "$TORII_USER" = "jane" &&
+            test "$TORII_TASK_INDEX" = "2"
+        "#);
+        cmd.env = Some(BTreeMap::from([("MY_VAR".to_string(), "my-value".to_string())]));
+
+        let mut ctx = ExecutionContext::new("section-1", "action-1", Some("jane")).with_run_id("run-1");
+        ctx.inherited_env = Some(Arc::new(test_env("TORII_TEST_INHERITED", "inherited")));
+
+        assert!(execute_command(&cmd, "{}", &ctx.with_task_index(2)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_with_clean_env_and_working_dir() {
+        let mut cmd = shell_command(r#"test -z "$TORII_TEST_NOT_INHERITED" && test "$(pwd)" = "/tmp""#);
+        cmd.inherit_env = Some(false);
+        cmd.working_dir = Some("/tmp".to_string());
+
+        let mut ctx = ExecutionContext::new("section-1", "action-1", None);
+        ctx.inherited_env = Some(Arc::new(test_env("TORII_TEST_NOT_INHERITED", "leaked")));
+
+        assert!(execute_command(&cmd, "{}", &ctx).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_input_modes() {
+        let payload = r#"{"field-1":"value-1"}"#;
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        let mut cmd = shell_command(r#"test "$0" = '{"field-1":"value-1"}'"#);
+        cmd.input_mode = Some(InputMode::Argv);
+        assert!(execute_command(&cmd, payload, &ctx).await.is_ok());
+
+        let mut cmd = shell_command(r#"test "$#" = "0" && test "$(cat)" = '{"field-1":"value-1"}'"#);
+        cmd.input_mode = Some(InputMode::Stdin);
+        assert!(execute_command(&cmd, payload, &ctx).await.is_ok());
+
+        let mut cmd = shell_command(r#"test "$(cat "$TORII_PAYLOAD_FILE")" = '{"field-1":"value-1"}'"#);
+        cmd.input_mode = Some(InputMode::File);
+        assert!(execute_command(&cmd, payload, &ctx).await.is_ok());
+
+        let mut cmd = shell_command(r#"test "$TORII_PAYLOAD" = '{"field-1":"value-1"}'"#);
+        cmd.input_mode = Some(InputMode::Env);
+        assert!(execute_command(&cmd, payload, &ctx).await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_execute_command_output() {
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        // the commands running in parallel each write their own output file
+        let first = shell_command(r#"sleep 0.2 && echo '{"task": "first"}' > "$TORII_OUTPUT_FILE""#);
+        let second = shell_command(r#"echo '{"task": "second"}' > "$TORII_OUTPUT_FILE""#);
+
+        let (first, second) = tokio::join!(
+            execute_command(&first, "{}", &ctx),
+            execute_command(&second, "{}", &ctx),
+        );
+
+        assert_eq!(first.unwrap().output, serde_json::json!({"task": "first"}));
+        assert_eq!(second.unwrap().output, serde_json::json!({"task": "second"}));
+
+        let no_output = shell_command("true");
+        assert_eq!(execute_command(&no_output, "{}", &ctx).await.unwrap().output, serde_json::json!({}));
+
+        let invalid_output = shell_command(r#"echo 'not json' > "$TORII_OUTPUT_FILE""#);
+        let err = execute_command(&invalid_output, "{}", &ctx).await.unwrap_err();
+        assert!(err.message().contains("invalid JSON output"), "{}", err);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_execute_command_with_execution_policy() {
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        let mut cmd = shell_command(r#"test "$(ulimit -n)" = "32" && test -d "$TMPDIR" && touch "$TMPDIR/file""#);
+        cmd.execution_policy = Some(ExecutionPolicyYamlConfig {
+            max_open_files: Some(32),
+            no_new_privileges: Some(true),
+            override_tmpdir: Some(true),
+            ..Default::default()
+        });
+        assert!(execute_command(&cmd, "{}", &ctx).await.is_ok());
+
+        let mut cmd = shell_command("while true; do echo 'this is a very verbose script'; done");
+        cmd.execution_policy = Some(ExecutionPolicyYamlConfig {
+            max_output_bytes: Some(1024),
+            ..Default::default()
+        });
+        let err = execute_command(&cmd, "{}", &ctx).await.unwrap_err();
+        assert!(err.message().contains("exceeded the output limit of 1024 bytes"), "{}", err);
+
+        let mut cmd = shell_command("while true; do :; done");
+        cmd.execution_policy = Some(ExecutionPolicyYamlConfig {
+            max_cpu_time_seconds: Some(1),
+            ..Default::default()
+        });
+        let err = execute_command(&cmd, "{}", &ctx).await.unwrap_err();
+        assert!(err.message().contains("CPU time limit of 1 seconds exceeded"), "{}", err);
+        assert!(!err.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_timeout_kills_process_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        let script = format!("sleep 30 & echo $! > {}; wait", pid_file.to_str().unwrap());
+
+        // timeout of the command
+        let mut cmd = shell_command(&script);
+        cmd.timeout = Some(1);
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+        let err = execute_command(&cmd, "{}", &ctx).await.unwrap_err();
+        assert!(err.message().contains("timed out after 1 seconds"), "{}", err);
+        assert!(err.is_timeout());
+
+        // the background process of the script is killed too - it may remain a zombie until reaped by init
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+
+        // deadline of the run, shorter than the timeout of the command
+        let cmd = shell_command(&script);
+        let ctx = ctx.with_deadline(Some(Instant::now() + Duration::from_secs(1)));
+        let start = Instant::now();
+        let err = execute_command(&cmd, "{}", &ctx).await.unwrap_err();
+        assert!(err.message().contains("timed out"), "{}", err);
+        assert!(err.is_timeout());
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_exit_code() {
+        let ctx = ExecutionContext::new("section-1", "action-1", None).with_task_index(1);
+
+        assert!(execute_command(&shell_command("exit 3"), "{}", &ctx).await.is_err());
+        assert_eq!(ctx.exit_code(), Some(3));
+
+        // each task gets its own exit code
+        assert_eq!(ctx.with_task_index(2).exit_code(), None);
+    }
+
+    #[test]
+    fn test_payload_hash() {
+        let payload = serde_json::json!({ "name": "my-env", "ttl": 24 });
+        let same_payload: serde_json::Value = serde_json::from_str(r#"{ "ttl": 24, "name": "my-env" }"#).unwrap();
+
+        assert_eq!(payload_hash(&payload), payload_hash(&same_payload));
+        assert_eq!(payload_hash(&payload).len(), 64);
+        assert_ne!(payload_hash(&payload), payload_hash(&serde_json::json!({ "name": "my-env", "ttl": 48 })));
+    }
+
+    #[test]
+    fn test_validation_errors_from_output() {
+        let line = |is_stderr: bool, message: &str| RunLogLine { is_stderr: Some(is_stderr), message: message.to_string() };
+
+        let lines = vec![
+            line(false, r#"{"message": "from stdout"}"#),
+            line(true, r#"{"message": "invalid environment", "field_errors": {"name": ["name already taken"]}}"#),
+            line(true, "Traceback (most recent call last):"),
+            line(false, r#"{"status": "ok"}"#),
+        ];
+
+        let errors = ValidationErrors::from_output(&lines).unwrap();
+        assert_eq!(errors.message, Some("invalid environment".to_string()));
+        assert_eq!(errors.field_errors, BTreeMap::from([("name".to_string(), vec!["name already taken".to_string()])]));
+
+        // stdout is only read when nothing was found on stderr
+        let errors = ValidationErrors::from_output(&lines[..1]).unwrap();
+        assert_eq!(errors.message, Some("from stdout".to_string()));
+
+        assert_eq!(ValidationErrors::from_output(&lines[2..]), None);
+    }
+
+    #[test]
+    fn test_list_runs_query_to_filters() {
+        let query = |query: &str| {
+            let uri = format!("/selfServiceSections/runs?{}", query).parse::<axum::http::Uri>().unwrap();
+            axum::extract::Query::<ListRunsQuery>::try_from_uri(&uri).unwrap().0
+        };
+
+        let filters = query("").to_filters(Some("section-1"), None).unwrap();
+        assert_eq!(filters.section_slug, Some("section-1".to_string()));
+        assert_eq!(filters.limit, 50);
+        assert_eq!(filters.sort, SortOrder::Desc);
+        assert!(!filters.include_input_payload && !filters.include_tasks);
+
+        let filters = query(
+            "limit=10&status=FAILURE&triggered_by=alice&created_after=2024-01-31T00:00:00&sort=asc\
+            &payload=%7B%22name%22%3A%22my-env%22%7D&include=tasks&cursor=1706659200000000_67e55044-10b1-426f-9247-bb680e5fe0c8"
+        ).to_filters(None, None).unwrap();
+
+        assert_eq!(filters.limit, 10);
+        assert!(matches!(filters.status, Some(Status::Failure)));
+        assert_eq!(filters.triggered_by, Some("alice".to_string()));
+        assert_eq!(filters.created_after.unwrap().to_string(), "2024-01-31 00:00:00");
+        assert_eq!(filters.sort, SortOrder::Asc);
+        assert_eq!(filters.payload, Some(serde_json::json!({ "name": "my-env" })));
+        assert!(!filters.include_input_payload && filters.include_tasks);
+        assert_eq!(filters.cursor.unwrap().0.to_string(), "2024-01-31 00:00:00");
+
+        assert!(query("limit=0").to_filters(None, None).is_err());
+        assert!(query("limit=501").to_filters(None, None).is_err());
+        assert!(query("payload=%5B1%5D").to_filters(None, None).is_err());
+        assert!(query("include=logs").to_filters(None, None).is_err());
+        assert!(query("cursor=abc").to_filters(None, None).is_err());
+    }
 }
@@ -0,0 +1,220 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, Pool, Postgres};
+use tracing::{error, info};
+
+use crate::config_reload::SharedConfig;
+use crate::constants::{DEFAULT_RETENTION_INTERVAL_IN_SECONDS, RETENTION_BATCH_SIZE};
+use crate::database::{delete_self_service_runs, list_self_service_run_logs_by_run_ids, list_self_service_runs_to_prune, SelfServiceRunJson, SelfServiceRunLogJson};
+use crate::metrics::metrics;
+use crate::yaml_config::RetentionYamlConfig;
+
+/// arbitrary key of the advisory lock held while pruning, so concurrent replicas don't archive the same runs
+const RETENTION_LOCK_KEY: i64 = 0x72_6574_656e;
+
+/// A pruned run as written in the archive files, one per line
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchivedRunJson {
+    #[serde(flatten)]
+    pub run: SelfServiceRunJson,
+    pub logs: Vec<SelfServiceRunLogJson>,
+}
+
+/// Periodically prune the runs according to the retention policy of the current config, nothing is pruned without one
+pub async fn retention_worker(pg_pool: Arc<Pool<Postgres>>, shared_config: Arc<SharedConfig>) {
+    let mut config_changes = shared_config.subscribe();
+
+    loop {
+        let retention = shared_config.current().self_service.retention.clone();
+
+        if let Some(retention) = &retention {
+            match prune_runs(&pg_pool, retention).await {
+                Ok(0) => {}
+                Ok(pruned) => info!("retention: {} runs pruned ({} since startup)", pruned, metrics().retention_pruned_runs.get()),
+                Err(err) => error!("retention: failed to prune runs: {}", err),
+            }
+        }
+
+        let next_prune = tokio::time::Instant::now() + retention.as_ref().map(|retention| retention.interval())
+            .unwrap_or(Duration::from_secs(DEFAULT_RETENTION_INTERVAL_IN_SECONDS));
+
+        // a reload changing the retention policy applies it right away
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(next_prune) => break,
+                Ok(_) = config_changes.changed() => {
+                    if config_changes.borrow_and_update().1.self_service.retention != retention {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Prune the runs batch by batch, return the number of pruned runs
+async fn prune_runs(pg_pool: &Pool<Postgres>, retention: &RetentionYamlConfig) -> Result<u64, String> {
+    let mut pruned = 0;
+
+    loop {
+        let batch = prune_runs_batch(pg_pool, retention).await?;
+
+        pruned += batch;
+
+        if batch < RETENTION_BATCH_SIZE as u64 {
+            return Ok(pruned);
+        }
+    }
+}
+
+/// Archive and delete one batch of runs in a transaction, return the number of pruned runs
+async fn prune_runs_batch(pg_pool: &Pool<Postgres>, retention: &RetentionYamlConfig) -> Result<u64, String> {
+    let mut conn = pg_pool.acquire().await.map_err(|err| err.to_string())?;
+    let mut tx = conn.begin().await.map_err(|err| err.to_string())?;
+
+    let (locked, ): (bool, ) = sqlx::query_as("SELECT pg_try_advisory_xact_lock($1)")
+        .bind(RETENTION_LOCK_KEY)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if !locked {
+        // another replica is pruning the runs
+        return Ok(0);
+    }
+
+    let runs = list_self_service_runs_to_prune(
+        &mut tx,
+        retention.max_age_days.map(|days| days as i64),
+        retention.max_runs_per_action.map(|count| count as i64),
+        retention.keep_last_failures.unwrap_or(0) as i64,
+        RETENTION_BATCH_SIZE,
+    ).await.map_err(|err| err.to_string())?;
+
+    if runs.is_empty() {
+        return Ok(0);
+    }
+
+    let ids = runs.iter().map(|run| run.uuid()).collect::<Vec<_>>();
+
+    if let Some(archive_dir) = &retention.archive_dir {
+        let logs = list_self_service_run_logs_by_run_ids(&mut tx, &ids).await.map_err(|err| err.to_string())?;
+
+        let archived_runs = runs.iter()
+            .map(|run| ArchivedRunJson {
+                run: run.to_json(),
+                logs: logs.iter().filter(|log| log.run_id() == run.uuid()).map(|log| log.to_json()).collect(),
+            })
+            .collect::<Vec<_>>();
+
+        let path = write_archive(Path::new(archive_dir), &archived_runs)?;
+
+        metrics().retention_archived_runs.inc_by(archived_runs.len() as u64);
+        info!("retention: {} runs archived to {} ({} since startup)", archived_runs.len(), path.display(), metrics().retention_archived_runs.get());
+    }
+
+    let pruned = delete_self_service_runs(&mut tx, &ids).await.map_err(|err| err.to_string())?;
+
+    tx.commit().await.map_err(|err| err.to_string())?;
+
+    metrics().retention_pruned_runs.inc_by(pruned);
+
+    Ok(pruned)
+}
+
+/// Write the runs to a new gzipped JSON Lines file, renamed once complete so a partial archive is never left behind
+fn write_archive(archive_dir: &Path, runs: &[ArchivedRunJson]) -> Result<PathBuf, String> {
+    let name = format!("runs-{}.jsonl.gz", chrono::Utc::now().format("%Y%m%dT%H%M%S%.6fZ"));
+    let path = archive_dir.join(&name);
+    let tmp_path = archive_dir.join(format!(".{}.tmp", name));
+
+    let file = File::create(&tmp_path)
+        .map_err(|err| format!("failed to create archive '{}': {}", tmp_path.display(), err))?;
+
+    let mut encoder = GzEncoder::new(file, Compression::default());
+
+    let result = runs.iter()
+        .try_for_each(|run| {
+            serde_json::to_writer(&mut encoder, run).map_err(std::io::Error::from)?;
+            encoder.write_all(b"\n")
+        })
+        .and_then(|_| encoder.finish())
+        .and_then(|file| file.sync_all())
+        .and_then(|_| std::fs::rename(&tmp_path, &path));
+
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(format!("failed to write archive '{}': {}", path.display(), err));
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use flate2::read::GzDecoder;
+
+    use crate::database::{SelfServiceRunJson, SelfServiceRunLogJson, Status};
+    use crate::self_service::retention::{ArchivedRunJson, write_archive};
+
+    fn archived_run(id: &str) -> ArchivedRunJson {
+        ArchivedRunJson {
+            run: SelfServiceRunJson {
+                id: id.to_string(),
+                created_at: "2024-01-01 00:00:00".to_string(),
+                updated_at: "2024-01-01 00:00:00".to_string(),
+                section_slug: "section".to_string(),
+                action_slug: "action".to_string(),
+                status: Status::Success,
+                input_payload: Some(serde_json::json!({"name": "test"})),
+                tasks: Some(serde_json::json!([])),
+                dry_run: false,
+                triggered_by: None,
+            },
+            logs: vec![SelfServiceRunLogJson {
+                id: "1".to_string(),
+                created_at: "2024-01-01 00:00:00".to_string(),
+                is_stderr: Some(false),
+                message: "done".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_archive() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = write_archive(dir.path(), &[archived_run("1"), archived_run("2")]).unwrap();
+
+        // only the complete archive is left in the directory
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert!(path.file_name().unwrap().to_str().unwrap().ends_with(".jsonl.gz"));
+
+        let lines = BufReader::new(GzDecoder::new(std::fs::File::open(&path).unwrap()))
+            .lines()
+            .map(|line| serde_json::from_str::<ArchivedRunJson>(&line.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].run.id, "1");
+        assert_eq!(lines[1].run.id, "2");
+        assert_eq!(lines[0].run.input_payload, Some(serde_json::json!({"name": "test"})));
+        assert_eq!(lines[0].logs[0].message, "done");
+    }
+
+    #[test]
+    fn test_write_archive_in_missing_directory() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(write_archive(&dir.path().join("missing"), &[archived_run("1")]).is_err());
+    }
+}
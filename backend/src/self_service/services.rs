@@ -1,111 +1,1318 @@
+use std::collections::BTreeMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
-use tokio::sync::mpsc::Receiver;
-use tracing::error;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tracing::{error, field, info, info_span, Instrument, warn};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::database::{Status, update_self_service_run};
-use crate::self_service::{execute_command, ExecValidateScriptRequest, JobOutputResult};
-use crate::yaml_config::{SelfServiceSectionActionPostValidateYamlConfig, SelfServiceSectionActionYamlConfig};
+use crate::config_reload::SharedConfig;
+use crate::constants::{LEASE_RENEW_INTERVAL_IN_SECONDS, VALIDATION_TTL_IN_SECONDS, WORKER_HEARTBEAT_INTERVAL_IN_SECONDS};
+use crate::health::Heartbeat;
+use crate::metrics::{label, metrics};
+use crate::database::{claim_expired_self_service_runs, claim_expired_self_service_validations, get_self_service_validation, insert_self_service_run_log, is_self_service_validation_expired, renew_self_service_run_leases, renew_self_service_validation_leases, SelfServiceRun, SelfServiceRunJson, Status, update_self_service_run, update_self_service_validation};
+use crate::self_service::condition::Condition;
+use crate::self_service::http_task::execute_http_task;
+use crate::self_service::template::{RenderedCommand, TemplateContext};
+use crate::self_service::{execute_command, ExecValidateScriptRequest, ExecutionContext, ExecutionError, find_self_service_action_by_slug, find_self_service_section_by_slug, JobOutputResult, payload_hash, RunLogLine, secret_field_slugs, ValidationErrors};
+use crate::yaml_config::{ExecutorYamlConfig, ExternalCommand, SelfServiceSectionActionPostValidateYamlConfig, SelfServiceSectionActionValidateYamlConfig, SelfServiceSectionActionYamlConfig, YamlConfig};
 
 #[derive(Serialize, Deserialize)]
 pub struct BackgroundWorkerTask {
     pub execution_status_id: String,
+    pub section_slug: String,
     pub self_service_section_action_yaml_config: SelfServiceSectionActionYamlConfig,
     pub req: ExecValidateScriptRequest,
+    /// version of the config the action was taken from, a reload of the config does not change the action of a queued run
+    #[serde(default)]
+    pub config_version: u64,
+    /// state of the tasks when the run is resumed after a restart of the backend - the finished ones are not executed again
+    pub previous_tasks: Vec<TaskPayload>,
+    /// trace of the request that queued the run, continued by the run
+    #[serde(skip)]
+    pub trace_context: Option<opentelemetry::Context>,
 }
 
 impl BackgroundWorkerTask {
     pub fn new(
         execution_status_id: String,
+        section_slug: String,
         self_service_section_action_yaml_config: SelfServiceSectionActionYamlConfig,
         req: ExecValidateScriptRequest,
     ) -> Self {
         Self {
             execution_status_id,
+            section_slug,
             self_service_section_action_yaml_config,
             req,
+            config_version: 0,
+            previous_tasks: vec![],
+            trace_context: None,
         }
     }
+
+    pub fn with_config_version(mut self, config_version: u64) -> Self {
+        self.config_version = config_version;
+        self
+    }
+
+    pub fn with_previous_tasks(mut self, previous_tasks: Vec<TaskPayload>) -> Self {
+        self.previous_tasks = previous_tasks;
+        self
+    }
+
+    pub fn with_trace_context(mut self, trace_context: opentelemetry::Context) -> Self {
+        self.trace_context = Some(trace_context);
+        self
+    }
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TaskStatus {
+    Pending,
+    Running,
+    Success,
+    Failure,
+    /// the condition of the task is false, or a task it depends on did not succeed
+    Skipped,
+    /// the task exceeded its timeout, or the run timed out before it started
+    TimedOut,
+}
+
+/// State of a node of the post_validate graph, stored in the `tasks` of the run (one entry per task, in the config order)
 #[derive(Serialize, Deserialize, Clone)]
 pub struct TaskPayload {
-    status: Status,
+    status: TaskStatus,
+    /// indexes of the tasks it depends on
+    #[serde(default)]
+    depends_on: Vec<usize>,
+    /// index of the task undone by this rollback task
+    #[serde(default)]
+    rollback_of: Option<usize>,
     message: Option<String>,
     post_validate_input: SelfServiceSectionActionPostValidateYamlConfig,
     post_validate_output: Option<JobOutputResult>,
+    #[serde(default)]
+    started_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// exit code of the command, when the executor knows it
+    #[serde(default)]
+    exit_code: Option<i32>,
+}
+
+impl TaskPayload {
+    fn new(post_validate_input: SelfServiceSectionActionPostValidateYamlConfig, depends_on: Vec<usize>) -> Self {
+        Self {
+            status: TaskStatus::Pending,
+            depends_on,
+            rollback_of: None,
+            message: None,
+            post_validate_input,
+            post_validate_output: None,
+            started_at: None,
+            finished_at: None,
+            exit_code: None,
+        }
+    }
+
+    fn start(&mut self) {
+        self.status = TaskStatus::Running;
+        self.started_at = Some(chrono::Utc::now());
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self.status, TaskStatus::Success | TaskStatus::Failure | TaskStatus::Skipped | TaskStatus::TimedOut)
+    }
+
+    /// a failed task stops the run unless it has `continue_on_error` - a failed rollback does not stop the other rollbacks
+    fn fails_run(&self) -> bool {
+        matches!(self.status, TaskStatus::Failure | TaskStatus::TimedOut)
+            && self.rollback_of.is_none()
+            && !self.post_validate_input.continue_on_error.unwrap_or(false)
+    }
+
+    fn finish(&mut self, result: Result<JobOutputResult, ExecutionError>, exit_code: Option<i32>) {
+        self.finished_at = Some(chrono::Utc::now());
+        self.exit_code = exit_code;
+
+        match result {
+            Ok(job_output_result) => {
+                self.status = TaskStatus::Success;
+                self.post_validate_output = Some(job_output_result);
+            }
+            Err(err) => {
+                self.status = if err.is_timeout() { TaskStatus::TimedOut } else { TaskStatus::Failure };
+                self.message = Some(err.to_string());
+            }
+        }
+    }
+
+    fn name(&self, index: usize) -> String {
+        self.post_validate_input.id.clone().unwrap_or_else(|| format!("#{}", index))
+    }
+
+    /// execution time reported by the executor, or time between the start and the end of the task
+    fn duration(&self) -> Option<Duration> {
+        if let Some(output) = &self.post_validate_output {
+            return Some(Duration::from_millis(output.execution_time_in_millis as u64));
+        }
+
+        (self.finished_at? - self.started_at?).to_std().ok()
+    }
+}
+
+/// Run with its tasks decoded
+#[derive(Serialize)]
+pub struct SelfServiceRunDetailJson {
+    #[serde(flatten)]
+    run: SelfServiceRunJson,
+    tasks: Vec<TaskPayload>,
+    /// ROLLED_BACK or ROLLBACK_FAILED when tasks were rolled back, also set when the run timed out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rollback_status: Option<Status>,
+}
+
+impl SelfServiceRunDetailJson {
+    pub fn new(run: &SelfServiceRun) -> Result<Self, String> {
+        let tasks = serde_json::from_value::<Vec<TaskPayload>>(run.tasks().clone())
+            .map_err(|err| format!("failed to decode the tasks of run '{}': {}", run.id(), err))?;
+
+        let mut run = run.to_json();
+        run.tasks = None;
+
+        let rollback_status = rollback_status(&tasks);
+
+        Ok(Self { run, tasks, rollback_status })
+    }
+}
+
+/// A timeout is reported as is, whatever the outcome of the rollback (see `rollback_status`)
+fn run_status(tasks: &[TaskPayload]) -> Status {
+    if !tasks.iter().any(|task| task.fails_run()) {
+        return Status::Success;
+    }
+
+    if tasks.iter().any(|task| task.fails_run() && task.status == TaskStatus::TimedOut) {
+        return Status::TimedOut;
+    }
+
+    rollback_status(tasks).unwrap_or(Status::Failure)
 }
 
-pub async fn background_worker(mut rx: Receiver<BackgroundWorkerTask>, pg_pool: Arc<Pool<Postgres>>) {
-    while let Some(task) = rx.recv().await {
-        let r = update_self_service_run(
-            &pg_pool,
-            task.execution_status_id.as_str(),
-            Status::Running,
-            &serde_json::Value::Array(vec![]),
-        ).await;
+/// Outcome of the rollback of a failed run, None if there was nothing to roll back
+fn rollback_status(tasks: &[TaskPayload]) -> Option<Status> {
+    let mut rollbacks = tasks.iter().filter(|task| task.rollback_of.is_some()).peekable();
 
-        if let Err(err) = r {
+    rollbacks.peek()?;
+
+    match rollbacks.all(|rollback| rollback.status == TaskStatus::Success) {
+        true => Some(Status::RolledBack),
+        false => Some(Status::RollbackFailed),
+    }
+}
+
+pub async fn background_worker(mut rx: Receiver<BackgroundWorkerTask>, pg_pool: Arc<Pool<Postgres>>, heartbeat: Arc<Heartbeat>) {
+    while let Some(task) = next_task(&mut rx, &heartbeat).await {
+        let busy_since = Instant::now();
+        heartbeat.set_busy(task.self_service_section_action_yaml_config.max_run_duration());
+        metrics().background_worker_busy.set(1);
+
+        execute_run(task, pg_pool.clone()).await;
+
+        heartbeat.set_idle();
+        metrics().background_worker_busy.set(0);
+        metrics().background_worker_busy_seconds.inc_by(busy_since.elapsed().as_secs_f64());
+    }
+}
+
+/// Execute the tasks of a run and its rollbacks, return the finished run
+pub async fn execute_run(mut task: BackgroundWorkerTask, pg_pool: Arc<Pool<Postgres>>) -> Option<SelfServiceRun> {
+    let action = &task.self_service_section_action_yaml_config;
+    let post_validate = action.post_validate.clone().unwrap_or_default();
+
+    // the graph is checked when the config is loaded
+    let dependencies = action.post_validate_dependencies().unwrap_or_default();
+
+    let mut tasks = post_validate.iter().zip(dependencies).enumerate()
+        .map(|(index, (cmd, depends_on))| match task.previous_tasks.get(index) {
+            Some(previous_task) if previous_task.is_done() => previous_task.clone(),
+            _ => TaskPayload::new(cmd.clone(), depends_on),
+        })
+        .collect::<Vec<_>>();
+
+    // the rollbacks done before a restart of the backend are not executed again
+    tasks.extend(task.previous_tasks.iter().skip(post_validate.len())
+        .filter(|previous_task| previous_task.rollback_of.is_some() && previous_task.is_done())
+        .cloned());
+
+    let r = update_self_service_run(
+        &pg_pool,
+        task.execution_status_id.as_str(),
+        Status::Running,
+        &serde_json::to_value(&tasks).unwrap(),
+    ).await;
+
+    if let Err(err) = r {
+        error!("failed to update action execution status: {}", err);
+        return None;
+    }
+
+    let started_at = chrono::Utc::now();
+
+    let run_span = info_span!(
+        "run",
+        otel.name = %format!("run {}/{}", task.section_slug, action.slug),
+        torii.run_id = %task.execution_status_id,
+        torii.section = %task.section_slug,
+        torii.action = %action.slug,
+        torii.dry_run = task.req.dry_run,
+        torii.config_version = task.config_version,
+        torii.status = field::Empty,
+    );
+
+    if let Some(trace_context) = task.trace_context.take() {
+        let _ = run_span.set_parent(trace_context);
+    }
+
+    let (log_tx, log_rx) = tokio::sync::mpsc::unbounded_channel::<RunLogLine>();
+    tokio::spawn(run_logs_writer(log_rx, pg_pool.clone(), task.execution_status_id.clone()));
+
+    let ctx = ExecutionContext::new(
+        task.section_slug.as_str(),
+        action.slug.as_str(),
+        task.req.triggered_by.as_deref(),
+    ).with_run_id(task.execution_status_id.as_str()).with_log_sink(log_tx).with_dry_run(task.req.dry_run);
+
+    let ctx = ctx.with_deadline(action.timeout.map(|timeout| run_deadline(timeout, &task.previous_tasks)));
+
+    let secret_fields = secret_field_slugs(action);
+    let run_id = task.execution_status_id.clone();
+
+    let on_change = |tasks_value| {
+        let pg_pool = pg_pool.clone();
+        let run_id = run_id.clone();
+
+        async move {
+            let _ = update_self_service_run(&pg_pool, run_id.as_str(), Status::Running, &tasks_value).await;
+        }
+    };
+
+    async {
+        execute_tasks(&mut tasks[..post_validate.len()], &post_validate, &task.req.payload, &secret_fields, &ctx, on_change).await;
+        rollback_tasks(&mut tasks, post_validate.len(), &task.req.payload, &secret_fields, &ctx, on_change).await;
+    }.instrument(run_span.clone()).await;
+
+    let status = run_status(&tasks);
+    run_span.record("torii.status", label(&status));
+
+    metrics().runs.with_label_values(&[&task.section_slug, &action.slug, &label(&status)]).inc();
+    record_task_durations(&task.section_slug, &action.slug, &tasks, started_at);
+
+    match update_self_service_run(
+        &pg_pool,
+        task.execution_status_id.as_str(),
+        status,
+        &serde_json::to_value(&tasks).unwrap(),
+    ).await {
+        Ok(run) => Some(run),
+        Err(err) => {
             error!("failed to update action execution status: {}", err);
-            continue;
+            None
         }
+    }
+}
 
-        let mut tasks = Vec::<TaskPayload>::new();
+/// Deadline of a run, counted from its first started task so that a run resumed after a restart does not get its whole timeout again
+fn run_deadline(timeout: u64, previous_tasks: &[TaskPayload]) -> Instant {
+    let elapsed = previous_tasks.iter()
+        .filter_map(|task| task.started_at)
+        .min()
+        .and_then(|started_at| (chrono::Utc::now() - started_at).to_std().ok())
+        .unwrap_or_default();
 
-        let mut last_task_value = serde_json::Value::Array(vec![]);
+    Instant::now() + Duration::from_secs(timeout).saturating_sub(elapsed)
+}
+
+/// Wait for the next task of a worker, beating while idle. None once all the senders are dropped.
+async fn next_task<T>(rx: &mut Receiver<T>, heartbeat: &Heartbeat) -> Option<T> {
+    let mut interval = tokio::time::interval(Duration::from_secs(WORKER_HEARTBEAT_INTERVAL_IN_SECONDS));
+
+    loop {
+        tokio::select! {
+            task = rx.recv() => return task,
+            _ = interval.tick() => heartbeat.beat(),
+        }
+    }
+}
 
-        for cmd in task.self_service_section_action_yaml_config.post_validate.as_ref().unwrap_or(&vec![]) {
-            let job_output_result = match execute_command(cmd, task.req.payload.to_string().as_str()).await {
-                Ok(job_output_result) => job_output_result,
-                Err(err) => {
-                    let task_payload = TaskPayload {
-                        status: Status::Failure,
-                        message: Some(err.to_string()),
-                        post_validate_input: cmd.clone(),
-                        post_validate_output: None,
-                    };
+/// Observe the duration of the tasks executed since `started_at`, the ones done before a restart of the backend are ignored
+fn record_task_durations(section_slug: &str, action_slug: &str, tasks: &[TaskPayload], started_at: chrono::DateTime<chrono::Utc>) {
+    let executed_tasks = tasks.iter().enumerate()
+        .filter(|(_, task)| task.finished_at.is_some_and(|finished_at| finished_at >= started_at));
 
-                    let _ = tasks.push(task_payload);
+    for (index, task) in executed_tasks {
+        if let Some(duration) = task.duration() {
+            metrics().task_duration
+                .with_label_values(&[section_slug, action_slug, &task.name(index), &label(&task.status)])
+                .observe(duration.as_secs_f64());
+        }
+    }
+}
 
-                    let _ = update_self_service_run(
-                        &pg_pool,
-                        task.execution_status_id.as_str(),
-                        Status::Failure,
-                        &serde_json::to_value(tasks.clone()).unwrap(),
-                    ).await;
+/// Execute the post_validate graph: a task starts once the tasks it depends on are done, independent tasks run in parallel.
+/// After a failure (without `continue_on_error`) no task starts anymore, the running ones are awaited.
+/// `on_change` receives the serialized tasks each time their state changes.
+async fn execute_tasks<F, Fut>(
+    tasks: &mut [TaskPayload],
+    post_validate: &[SelfServiceSectionActionPostValidateYamlConfig],
+    payload: &serde_json::Value,
+    secret_fields: &[String],
+    ctx: &ExecutionContext,
+    mut on_change: F,
+) where F: FnMut(serde_json::Value) -> Fut, Fut: Future<Output=()> {
+    // outputs of the tasks with an id, available to the placeholders of the tasks depending on them
+    let mut outputs = BTreeMap::new();
 
-                    break;
+    for finished_task in tasks.iter() {
+        record_task_output(&mut outputs, finished_task);
+    }
+
+    let mut running = JoinSet::new();
+
+    loop {
+        // start the tasks whose dependencies are done, or skip them, until no task changes:
+        // a task can depend on a task declared after it
+        loop {
+            let mut changed = false;
+
+            for index in 0..tasks.len() {
+                if tasks[index].status != TaskStatus::Pending || !tasks[index].depends_on.iter().all(|&dependency| tasks[dependency].is_done()) {
+                    continue;
                 }
-            };
 
-            // TODO pass output to next command
+                // every task ready is started, skipped or timed out
+                changed = true;
 
-            let task_payload = TaskPayload {
-                status: Status::Success,
-                message: None,
-                post_validate_input: cmd.clone(),
-                post_validate_output: Some(job_output_result),
-            };
+                let cmd = &post_validate[index];
+                let template_ctx = TemplateContext { fields: payload, outputs: &outputs, secret_fields };
 
-            let _ = tasks.push(task_payload);
+                let failed_dependency = tasks[index].depends_on.iter()
+                    .find(|&&dependency| tasks[dependency].status == TaskStatus::Skipped || tasks[dependency].fails_run());
+
+                let skip_reason = if let Some(&dependency) = failed_dependency {
+                    Some(format!("skipped: task '{}' did not succeed", tasks[dependency].name(dependency)))
+                } else if tasks.iter().any(|task| task.fails_run()) {
+                    Some("skipped: the run failed".to_string())
+                } else if ctx.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    tasks[index].status = TaskStatus::TimedOut;
+                    tasks[index].message = Some("the run timed out before the task started".to_string());
+                    continue;
+                } else if ctx.dry_run && !cmd.supports_dry_run.unwrap_or(false) {
+                    Some("skipped: the task does not support dry runs".to_string())
+                } else {
+                    match cmd.when.as_deref().map(|when| (when, Condition::parse(when).map(|condition| condition.evaluate(&template_ctx)))) {
+                        Some((when, Ok(false))) => Some(format!("skipped: condition '{}' is false", when)),
+                        Some((_, Err(err))) => Some(format!("skipped: {}", err)),
+                        _ => None,
+                    }
+                };
+
+                if let Some(skip_reason) = skip_reason {
+                    info!("task '{}' {}", tasks[index].name(index), &skip_reason);
+                    tasks[index].status = TaskStatus::Skipped;
+                    tasks[index].message = Some(skip_reason);
+                    continue;
+                }
 
-            last_task_value = serde_json::to_value(tasks.clone()).unwrap();
+                tasks[index].start();
 
-            let _ = update_self_service_run(
-                &pg_pool,
-                task.execution_status_id.as_str(),
-                Status::Running,
-                &last_task_value,
-            ).await;
+                let cmd = cmd.clone();
+                let payload = payload.clone();
+                let outputs = outputs.clone();
+                let secret_fields = secret_fields.to_vec();
+                let task_ctx = ctx.with_task_index(index);
+
+                running.spawn(async move {
+                    let template_ctx = TemplateContext { fields: &payload, outputs: &outputs, secret_fields: &secret_fields };
+                    let result = execute_post_validate_task(&cmd, &template_ctx, &task_ctx).await;
+                    (index, result, task_ctx.exit_code())
+                }.in_current_span());
+            }
+
+            if !changed {
+                break;
+            }
         }
 
-        let _ = update_self_service_run(
-            &pg_pool,
-            task.execution_status_id.as_str(),
-            Status::Success,
-            &last_task_value,
-        ).await;
+        on_change(serde_json::to_value(&*tasks).unwrap()).await;
+
+        let (index, result, exit_code) = match running.join_next().await {
+            Some(Ok(finished)) => finished,
+            Some(Err(err)) => {
+                error!("task aborted: {}", err);
+                continue;
+            }
+            None => break,
+        };
+
+        tasks[index].finish(result, exit_code);
+        record_task_output(&mut outputs, &tasks[index]);
+    }
+
+    // a task that panicked is still running, the tasks depending on it were never started
+    for task_payload in tasks.iter_mut().filter(|task_payload| !task_payload.is_done()) {
+        let (status, message) = match task_payload.status {
+            TaskStatus::Running => (TaskStatus::Failure, "aborted"),
+            _ => (TaskStatus::Skipped, "skipped: a task it depends on was aborted"),
+        };
+
+        task_payload.status = status;
+        task_payload.message = Some(message.to_string());
+    }
+}
+
+/// Undo the successful tasks once a task made the run fail, in the reverse order of the graph: a task is undone after the
+/// tasks depending on it. Each rollback is added to the tasks, a failed rollback does not prevent the next ones.
+async fn rollback_tasks<F, Fut>(
+    tasks: &mut Vec<TaskPayload>,
+    task_count: usize,
+    payload: &serde_json::Value,
+    secret_fields: &[String],
+    ctx: &ExecutionContext,
+    mut on_change: F,
+) where F: FnMut(serde_json::Value) -> Fut, Fut: Future<Output=()> {
+    if !tasks[..task_count].iter().any(|task| task.fails_run()) {
+        return;
+    }
+
+    // the rollbacks are executed even if the run timed out
+    let rollback_ctx = ctx.clone().with_deadline(None);
+
+    let mut outputs = BTreeMap::new();
+
+    for finished_task in tasks.iter() {
+        record_task_output(&mut outputs, finished_task);
+    }
+
+    for index in topological_order(&tasks[..task_count]).into_iter().rev() {
+        let rollback = match (tasks[index].status, &tasks[index].post_validate_input.rollback) {
+            (TaskStatus::Success, Some(rollback)) => rollback.as_ref().clone(),
+            _ => continue,
+        };
+
+        if ctx.dry_run && !rollback.supports_dry_run.unwrap_or(false) {
+            continue;
+        }
+
+        if tasks[task_count..].iter().any(|task| task.rollback_of == Some(index)) {
+            continue;
+        }
+
+        info!("rolling back task '{}'", tasks[index].name(index));
+
+        let mut rollback_task = TaskPayload::new(rollback.clone(), vec![]);
+        rollback_task.start();
+        rollback_task.rollback_of = Some(index);
+        tasks.push(rollback_task);
+
+        let rollback_index = tasks.len() - 1;
+        on_change(serde_json::to_value(&*tasks).unwrap()).await;
+
+        let template_ctx = TemplateContext { fields: payload, outputs: &outputs, secret_fields };
+
+        let task_ctx = rollback_ctx.with_task_index(rollback_index);
+        let result = execute_post_validate_task(&rollback, &template_ctx, &task_ctx).await;
+        tasks[rollback_index].finish(result, task_ctx.exit_code());
+
+        on_change(serde_json::to_value(&*tasks).unwrap()).await;
+    }
+}
+
+/// Indexes of the tasks, each task after the tasks it depends on
+fn topological_order(tasks: &[TaskPayload]) -> Vec<usize> {
+    let mut order = vec![];
+
+    while order.len() < tasks.len() {
+        match (0..tasks.len()).find(|index| !order.contains(index) && tasks[*index].depends_on.iter().all(|dependency| order.contains(dependency))) {
+            Some(index) => order.push(index),
+            // cycles are rejected when the config is loaded
+            None => break,
+        }
+    }
+
+    order
+}
+
+/// Execute the command or the HTTP request of a task
+async fn execute_post_validate_task(
+    cmd: &SelfServiceSectionActionPostValidateYamlConfig,
+    template_ctx: &TemplateContext<'_>,
+    ctx: &ExecutionContext,
+) -> Result<JobOutputResult, ExecutionError> {
+    let task_timeout = ctx.timeout(cmd.get_timeout());
+    let name = cmd.id.clone().unwrap_or_else(|| format!("#{}", ctx.task_index));
+
+    let span = info_span!(
+        "task",
+        otel.name = %format!("task {}", name),
+        torii.task = %name,
+        torii.task_index = ctx.task_index,
+        otel.status_code = field::Empty,
+        otel.status_message = field::Empty,
+    );
+
+    let result = async {
+        match &cmd.http {
+            Some(http) => execute_http_task(http, task_timeout, template_ctx, ctx).await,
+            None => match RenderedCommand::new(cmd, template_ctx) {
+                Ok(rendered_cmd) => execute_command(&rendered_cmd, template_ctx.fields.to_string().as_str(), ctx).await,
+                Err(err) => Err(err.into()),
+            },
+        }
+    }.instrument(span.clone()).await;
+
+    if let Err(err) = &result {
+        span.record("otel.status_code", "ERROR");
+        span.record("otel.status_message", err.message());
+    }
+
+    result
+}
+
+fn record_task_output(outputs: &mut BTreeMap<String, serde_json::Value>, task_payload: &TaskPayload) {
+    if let (TaskStatus::Success, Some(id), Some(job_output_result)) =
+        (task_payload.status, &task_payload.post_validate_input.id, &task_payload.post_validate_output) {
+        outputs.insert(id.clone(), job_output_result.output.clone());
+    }
+}
+
+/// Store the lines written by the commands of a run until all the senders are dropped
+async fn run_logs_writer(mut log_rx: UnboundedReceiver<RunLogLine>, pg_pool: Arc<Pool<Postgres>>, run_id: String) {
+    while let Some(line) = log_rx.recv().await {
+        if let Err(err) = insert_self_service_run_log(&pg_pool, run_id.as_str(), line.is_stderr, line.message.as_str()).await {
+            error!("failed to store run log: {}", err);
+        }
+    }
+}
+
+/// Identifies this backend as the owner of the runs and validations it executes
+#[derive(Clone)]
+pub struct InstanceId(pub String);
+
+/// Renew the leases of the runs and validations of this backend, the other replicas take them over otherwise
+pub async fn lease_renewer(pg_pool: Arc<Pool<Postgres>>, instance_id: InstanceId) {
+    let mut interval = tokio::time::interval(Duration::from_secs(LEASE_RENEW_INTERVAL_IN_SECONDS));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = renew_self_service_run_leases(&pg_pool, &instance_id.0).await {
+            error!("failed to renew the leases of the runs: {}", err);
+        }
+
+        if let Err(err) = renew_self_service_validation_leases(&pg_pool, &instance_id.0).await {
+            error!("failed to renew the leases of the validations: {}", err);
+        }
+    }
+}
+
+/// Take over the runs and validations whose lease expired, periodically since a stopped replica does not hand its runs over
+pub async fn reconcile_worker(
+    pg_pool: Arc<Pool<Postgres>>,
+    shared_config: Arc<SharedConfig>,
+    tx: Sender<BackgroundWorkerTask>,
+    instance_id: InstanceId,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(LEASE_RENEW_INTERVAL_IN_SECONDS));
+
+    loop {
+        interval.tick().await;
+        reconcile_validations(&pg_pool, &instance_id).await;
+        reconcile_runs(&pg_pool, &shared_config, &tx, &instance_id).await;
+    }
+}
+
+/// Bring back the runs interrupted by a restart or a crash of their backend, once their lease expired:
+/// - queued runs are sent again to the background worker
+/// - running runs are resumed, the finished tasks are kept and the tasks waiting on a Kubernetes Job attach to the existing Job
+/// - the other running tasks are marked as failed, their process died with the previous backend
+async fn reconcile_runs(pg_pool: &Pool<Postgres>, shared_config: &SharedConfig, tx: &Sender<BackgroundWorkerTask>, instance_id: &InstanceId) {
+    let runs = match claim_expired_self_service_runs(pg_pool, &instance_id.0).await {
+        Ok(runs) => runs,
+        Err(err) => {
+            error!("failed to claim the runs to reconcile: {}", err);
+            return;
+        }
+    };
+
+    let (config_version, yaml_config) = shared_config.current_with_version();
+
+    for run in runs {
+        if let Err(err) = reconcile_run(&yaml_config, config_version, tx, &run).await {
+            warn!("run '{}' can't be resumed: {}", run.id(), err);
+
+            let _ = update_self_service_run(pg_pool, run.id().as_str(), Status::Failure, run.tasks()).await;
+        }
+    }
+}
+
+async fn reconcile_run(
+    yaml_config: &YamlConfig,
+    config_version: u64,
+    tx: &Sender<BackgroundWorkerTask>,
+    run: &SelfServiceRun,
+) -> Result<(), String> {
+    let action = find_self_service_section_by_slug(&yaml_config.self_service.sections, run.section_slug())
+        .and_then(|section| find_self_service_action_by_slug(section, run.action_slug()))
+        .ok_or_else(|| format!("action '{}/{}' does not exist anymore", run.section_slug(), run.action_slug()))?;
+
+    let mut tasks = serde_json::from_value::<Vec<TaskPayload>>(run.tasks().clone()).unwrap_or_default();
+
+    if let Status::Running = run.status() {
+        // a command running as a local process or a container died with the previous backend,
+        // a Kubernetes Job is still running and the executor attaches to it when the task starts again
+        for task_payload in tasks.iter_mut().filter(|task_payload| task_payload.status == TaskStatus::Running) {
+            if !matches!(task_payload.post_validate_input.get_executor(), Some(ExecutorYamlConfig::Kubernetes(_))) {
+                task_payload.status = TaskStatus::Failure;
+                task_payload.message = Some("interrupted by a restart of the backend".to_string());
+            }
+        }
+    }
+
+    info!("resuming run '{}' of action '{}/{}'", run.id(), run.section_slug(), run.action_slug());
+
+    let req = ExecValidateScriptRequest {
+        payload: run.input_payload().clone(),
+        triggered_by: run.triggered_by().map(|triggered_by| triggered_by.to_string()),
+        validation_id: None,
+        dry_run: run.dry_run(),
+    };
+
+    let task = BackgroundWorkerTask::new(run.id(), run.section_slug().to_string(), action.clone(), req)
+        .with_config_version(config_version)
+        .with_previous_tasks(tasks);
+
+    tx.send(task).await.map_err(|err| format!("failed to send task to background worker: {}", err))
+}
+
+pub struct ValidationWorkerTask {
+    pub validation_id: String,
+    pub section_slug: String,
+    pub self_service_section_action_yaml_config: SelfServiceSectionActionYamlConfig,
+    pub req: ExecValidateScriptRequest,
+    /// trace of the request that queued the validation, continued by the validation
+    pub trace_context: opentelemetry::Context,
+}
+
+/// State of a validate script, stored in the `tasks` of the validation (one entry per script, in the config order)
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ValidationScriptPayload {
+    status: TaskStatus,
+    message: Option<String>,
+    /// errors reported by the script for each field
+    #[serde(default)]
+    field_errors: BTreeMap<String, Vec<String>>,
+    validate_input: SelfServiceSectionActionValidateYamlConfig,
+    validate_output: Option<JobOutputResult>,
+}
+
+impl ValidationScriptPayload {
+    pub fn new(validate_input: SelfServiceSectionActionValidateYamlConfig) -> Self {
+        Self {
+            status: TaskStatus::Pending,
+            message: None,
+            field_errors: BTreeMap::new(),
+            validate_input,
+            validate_output: None,
+        }
+    }
+
+    fn has_failed(&self) -> bool {
+        matches!(self.status, TaskStatus::Failure | TaskStatus::TimedOut)
+    }
+
+    /// errors of the script if it did not succeed
+    pub fn errors(&self) -> Option<ValidationErrors> {
+        match self.has_failed() {
+            true => Some(ValidationErrors {
+                message: self.message.clone(),
+                field_errors: self.field_errors.clone(),
+            }),
+            false => None,
+        }
+    }
+}
+
+fn validation_status(scripts: &[ValidationScriptPayload]) -> Status {
+    match scripts.iter().find(|script| script.has_failed()) {
+        None => Status::Success,
+        Some(script) if script.status == TaskStatus::TimedOut => Status::TimedOut,
+        Some(_) => Status::Failure,
+    }
+}
+
+/// Execute the validations requested asynchronously - unlike the runs, they don't wait for each other,
+/// up to `max_concurrent_validations` at a time
+pub async fn validation_worker(
+    mut rx: Receiver<ValidationWorkerTask>,
+    pg_pool: Arc<Pool<Postgres>>,
+    heartbeat: Arc<Heartbeat>,
+    max_concurrent_validations: usize,
+) {
+    let semaphore = Arc::new(Semaphore::new(max_concurrent_validations));
+
+    while let Some(task) = next_task(&mut rx, &heartbeat).await {
+        // the next validations stay queued until one of the running validations is over
+        let Some(permit) = next_permit(&semaphore, &heartbeat).await else {
+            break;
+        };
+
+        let span = info_span!(
+            "validation",
+            otel.name = %format!("validation {}/{}", task.section_slug, task.self_service_section_action_yaml_config.slug),
+            torii.validation_id = %task.validation_id,
+        );
+
+        let _ = span.set_parent(task.trace_context.clone());
+
+        let pg_pool = pg_pool.clone();
+
+        tokio::spawn(async move {
+            run_validation(task, pg_pool).await;
+            drop(permit);
+        }.instrument(span));
+    }
+}
+
+/// Wait for a permit of the semaphore, beating while waiting. None once the semaphore is closed.
+async fn next_permit(semaphore: &Arc<Semaphore>, heartbeat: &Heartbeat) -> Option<OwnedSemaphorePermit> {
+    let mut interval = tokio::time::interval(Duration::from_secs(WORKER_HEARTBEAT_INTERVAL_IN_SECONDS));
+    let permit = semaphore.clone().acquire_owned();
+    tokio::pin!(permit);
+
+    loop {
+        tokio::select! {
+            permit = &mut permit => return permit.ok(),
+            _ = interval.tick() => heartbeat.beat(),
+        }
+    }
+}
+
+async fn run_validation(task: ValidationWorkerTask, pg_pool: Arc<Pool<Postgres>>) {
+    let action = &task.self_service_section_action_yaml_config;
+
+    let mut scripts = action.validate.iter().flatten()
+        .map(|cmd| ValidationScriptPayload::new(cmd.clone()))
+        .collect::<Vec<_>>();
+
+    let ctx = ExecutionContext::new(
+        task.section_slug.as_str(),
+        action.slug.as_str(),
+        task.req.triggered_by.as_deref(),
+    );
+
+    let secret_fields = secret_field_slugs(action);
+    let validation_id = task.validation_id.clone();
+
+    metrics().validations_running.inc();
+
+    execute_validate_scripts(&mut scripts, &task.req.payload, &secret_fields, &ctx, |scripts_value| {
+        let pg_pool = pg_pool.clone();
+        let validation_id = validation_id.clone();
+
+        async move {
+            let _ = update_self_service_validation(&pg_pool, validation_id.as_str(), Status::Running, &scripts_value).await;
+        }
+    }).await;
+
+    metrics().validations_running.dec();
+
+    if let Err(err) = update_self_service_validation(
+        &pg_pool,
+        task.validation_id.as_str(),
+        validation_status(&scripts),
+        &serde_json::to_value(&scripts).unwrap(),
+    ).await {
+        error!("failed to update validation '{}': {}", task.validation_id, err);
+    }
+}
+
+/// Execute the validate scripts one after the other, the scripts following a failed one are skipped.
+/// `on_change` receives the serialized scripts each time their state changes.
+pub async fn execute_validate_scripts<F, Fut>(
+    scripts: &mut [ValidationScriptPayload],
+    payload: &serde_json::Value,
+    secret_fields: &[String],
+    ctx: &ExecutionContext,
+    mut on_change: F,
+) where F: FnMut(serde_json::Value) -> Fut, Fut: Future<Output=()> {
+    let outputs = BTreeMap::new();
+    let template_ctx = TemplateContext { fields: payload, outputs: &outputs, secret_fields };
+
+    for index in 0..scripts.len() {
+        if scripts[..index].iter().any(|script| script.has_failed()) {
+            scripts[index].status = TaskStatus::Skipped;
+            scripts[index].message = Some("skipped: a previous validate script failed".to_string());
+            continue;
+        }
+
+        scripts[index].status = TaskStatus::Running;
+        on_change(serde_json::to_value(&*scripts).unwrap()).await;
+
+        let (log_tx, mut log_rx) = tokio::sync::mpsc::unbounded_channel::<RunLogLine>();
+        let script_ctx = ctx.with_task_index(index).with_log_sink(log_tx);
+
+        let cmd = &scripts[index].validate_input;
+
+        let result = match RenderedCommand::new(cmd, &template_ctx) {
+            Ok(rendered_cmd) => execute_command(&rendered_cmd, payload.to_string().as_str(), &script_ctx).await,
+            Err(err) => Err(err.into()),
+        };
+
+        // the output is read until the script closes it, a process left in the background gets a short delay
+        drop(script_ctx);
+        let mut lines = vec![];
+
+        let _ = tokio::time::timeout(Duration::from_secs(1), async {
+            while let Some(line) = log_rx.recv().await {
+                lines.push(line);
+            }
+        }).await;
+
+        match result {
+            Ok(job_output_result) => {
+                scripts[index].status = TaskStatus::Success;
+                scripts[index].validate_output = Some(job_output_result);
+            }
+            Err(err) => {
+                let errors = ValidationErrors::from_output(&lines).unwrap_or_default();
+
+                scripts[index].status = if err.is_timeout() { TaskStatus::TimedOut } else { TaskStatus::Failure };
+                scripts[index].message = Some(errors.message.unwrap_or(err.to_string()));
+                scripts[index].field_errors = errors.field_errors;
+            }
+        }
+    }
+
+    on_change(serde_json::to_value(&*scripts).unwrap()).await;
+}
+
+/// Check the validation given to execute an action: it must be a recent successful validation of the same action and payload.
+/// Without validation id, the execution is only refused if the action has `require_validation`.
+pub async fn check_validation(
+    pg_pool: &Pool<Postgres>,
+    section_slug: &str,
+    action: &SelfServiceSectionActionYamlConfig,
+    req: &ExecValidateScriptRequest,
+) -> Result<(), String> {
+    let validation_id = match (&req.validation_id, action.require_validation.unwrap_or(false)) {
+        (Some(validation_id), _) => validation_id,
+        (None, true) => return Err(format!("Action '{}' requires a successful validation of the payload", action.slug)),
+        (None, false) => return Ok(()),
+    };
+
+    let validation = get_self_service_validation(pg_pool, validation_id).await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| format!("Validation '{}' not found", validation_id))?;
+
+    if validation.section_slug() != section_slug || validation.action_slug() != action.slug {
+        return Err(format!("Validation '{}' is not a validation of action '{}'", validation_id, action.slug));
+    }
+
+    if !matches!(validation.status(), Status::Success) {
+        return Err(format!("Validation '{}' did not succeed", validation_id));
+    }
+
+    if validation.payload_hash() != payload_hash(&req.payload) {
+        return Err(format!("Validation '{}' was done with a different payload", validation_id));
+    }
+
+    let expired = is_self_service_validation_expired(pg_pool, validation_id, VALIDATION_TTL_IN_SECONDS).await
+        .map_err(|err| err.to_string())?;
+
+    if expired {
+        return Err(format!("Validation '{}' expired, validate the payload again", validation_id));
+    }
+
+    Ok(())
+}
+
+/// The validations whose lease expired are marked as failed, their scripts died with their backend
+async fn reconcile_validations(pg_pool: &Pool<Postgres>, instance_id: &InstanceId) {
+    let validations = match claim_expired_self_service_validations(pg_pool, &instance_id.0).await {
+        Ok(validations) => validations,
+        Err(err) => {
+            error!("failed to claim the validations to reconcile: {}", err);
+            return;
+        }
+    };
+
+    for validation in validations {
+        let mut scripts = serde_json::from_value::<Vec<ValidationScriptPayload>>(validation.tasks().clone()).unwrap_or_default();
+
+        for script in scripts.iter_mut().filter(|script| script.status == TaskStatus::Running) {
+            script.status = TaskStatus::Failure;
+            script.message = Some("interrupted by a restart of the backend".to_string());
+        }
+
+        warn!("validation '{}' was interrupted by a restart of the backend", validation.id());
+
+        let _ = update_self_service_validation(pg_pool, validation.id().as_str(), Status::Failure, &serde_json::to_value(&scripts).unwrap()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+    use tokio::sync::Semaphore;
+
+    use crate::database::Status;
+    use crate::health::Heartbeat;
+    use crate::self_service::{ExecValidateScriptRequest, ExecutionContext};
+    use crate::self_service::services::{check_validation, execute_tasks, execute_validate_scripts, next_permit, rollback_status, rollback_tasks, run_deadline, run_status, TaskPayload, TaskStatus, validation_status, ValidationScriptPayload};
+    use crate::yaml_config::{SelfServiceSectionActionPostValidateYamlConfig, SelfServiceSectionActionValidateYamlConfig, SelfServiceSectionActionYamlConfig};
+
+    fn shell_task(id: &str, script: &str, depends_on: &[&str]) -> SelfServiceSectionActionPostValidateYamlConfig {
+        SelfServiceSectionActionPostValidateYamlConfig {
+            id: Some(id.to_string()),
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            http: None,
+            depends_on: Some(depends_on.iter().map(|id| id.to_string()).collect()),
+            when: None,
+            continue_on_error: None,
+            rollback: None,
+            supports_dry_run: None,
+            timeout: None,
+            env: None,
+            working_dir: None,
+            inherit_env: None,
+            input_mode: None,
+            execution_policy: None,
+            executor: None,
+            output_model: None,
+        }
+    }
+
+    fn shell_rollback(script: &str) -> Box<SelfServiceSectionActionPostValidateYamlConfig> {
+        let mut rollback = shell_task("", script, &[]);
+        rollback.id = None;
+        rollback.depends_on = None;
+        Box::new(rollback)
+    }
+
+    fn pending_tasks(post_validate: &[SelfServiceSectionActionPostValidateYamlConfig], dependencies: &[Vec<usize>]) -> Vec<TaskPayload> {
+        post_validate.iter().zip(dependencies).map(|(cmd, depends_on)| TaskPayload::new(cmd.clone(), depends_on.clone())).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_execute_tasks_in_parallel() {
+        let post_validate = vec![
+            shell_task("create-dns", "sleep 1", &[]),
+            shell_task("create-bucket", "sleep 1", &[]),
+            shell_task("notify", "exit 0", &["create-dns", "create-bucket"]),
+        ];
+
+        let mut tasks = pending_tasks(&post_validate, &[vec![], vec![], vec![0, 1]]);
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        let start = Instant::now();
+        execute_tasks(&mut tasks, &post_validate, &json!({}), &[], &ctx, |_| async {}).await;
+
+        assert!(start.elapsed().as_millis() < 1900, "independent tasks did not run in parallel");
+        assert!(tasks.iter().all(|task| task.status == TaskStatus::Success));
+        assert!(matches!(run_status(&tasks), Status::Success));
+    }
+
+    #[tokio::test]
+    async fn test_execute_tasks_with_conditions_and_failures() {
+        let mut post_validate = vec![
+            shell_task("soft-failure", "exit 1", &[]),
+            shell_task("after-soft-failure", "exit 0", &["soft-failure"]),
+            shell_task("not-seeded", "exit 0", &[]),
+            shell_task("hard-failure", "exit 1", &["after-soft-failure"]),
+            shell_task("after-hard-failure", "exit 0", &["hard-failure"]),
+        ];
+
+        post_validate[0].continue_on_error = Some(true);
+        post_validate[1].when = Some("fields.seed == true".to_string());
+        post_validate[2].when = Some("!fields.seed".to_string());
+
+        let mut tasks = pending_tasks(&post_validate, &[vec![], vec![0], vec![], vec![1], vec![3]]);
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        let mut changes = 0;
+        execute_tasks(&mut tasks, &post_validate, &json!({ "seed": true }), &[], &ctx, |_| {
+            changes += 1;
+            async {}
+        }).await;
+
+        let statuses = tasks.iter().map(|task| task.status).collect::<Vec<_>>();
+        assert_eq!(statuses, vec![TaskStatus::Failure, TaskStatus::Success, TaskStatus::Skipped, TaskStatus::Failure, TaskStatus::Skipped]);
+        assert_eq!(tasks[2].message.as_deref(), Some("skipped: condition '!fields.seed' is false"));
+        assert_eq!(tasks[4].message.as_deref(), Some("skipped: task 'hard-failure' did not succeed"));
+        assert!(matches!(run_status(&tasks), Status::Failure));
+        assert!(changes > 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_tasks_with_forward_dependencies() {
+        let mut post_validate = vec![
+            shell_task("notify", "exit 0", &["seed-db"]),
+            shell_task("seed-db", "exit 0", &[]),
+        ];
+
+        post_validate[1].when = Some("fields.seed == true".to_string());
+
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        // no task is running once seed-db is skipped, notify is skipped because of it, not because of an aborted task
+        let mut tasks = pending_tasks(&post_validate, &[vec![1], vec![]]);
+        execute_tasks(&mut tasks, &post_validate, &json!({ "seed": false }), &[], &ctx, |_| async {}).await;
+
+        assert_eq!(tasks[0].status, TaskStatus::Skipped);
+        assert_eq!(tasks[0].message.as_deref(), Some("skipped: task 'seed-db' did not succeed"));
+        assert_eq!(tasks[1].status, TaskStatus::Skipped);
+
+        let mut tasks = pending_tasks(&post_validate, &[vec![1], vec![]]);
+        execute_tasks(&mut tasks, &post_validate, &json!({ "seed": true }), &[], &ctx, |_| async {}).await;
+
+        assert!(tasks.iter().all(|task| task.status == TaskStatus::Success));
+    }
+
+    #[tokio::test]
+    async fn test_execute_tasks_with_outputs() {
+        let mut post_validate = vec![
+            shell_task("create-db", r#"echo "{\"url\": \"postgres://db/$1\"}" > "$TORII_OUTPUT_FILE""#, &[]),
+            shell_task("seed-db", r#"test "$1" = "postgres://db/my-env""#, &["create-db"]),
+        ];
+
+        // the arguments are rendered per run, with the fields and the outputs of the previous tasks
+        post_validate[0].command.extend(["create-db".to_string(), "{{ fields.name }}".to_string()]);
+        post_validate[1].command.extend(["seed-db".to_string(), "{{ outputs.create-db.url }}".to_string()]);
+
+        let mut tasks = pending_tasks(&post_validate, &[vec![], vec![0]]);
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        execute_tasks(&mut tasks, &post_validate, &json!({ "name": "my-env" }), &[], &ctx, |_| async {}).await;
+
+        assert_eq!(tasks[0].post_validate_output.as_ref().unwrap().output, json!({ "url": "postgres://db/my-env" }));
+        assert_eq!(tasks[1].status, TaskStatus::Success, "{:?}", tasks[1].message);
+        assert!(matches!(run_status(&tasks), Status::Success));
+    }
+
+    #[tokio::test]
+    async fn test_rollback_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = dir.path().join("journal");
+        let journal = journal.to_str().unwrap();
+
+        let mut post_validate = vec![
+            shell_task("create-db", "exit 0", &[]),
+            shell_task("create-bucket", "exit 0", &["create-db"]),
+            shell_task("notify", "exit 0", &["create-bucket"]),
+            shell_task("deploy", "exit 1", &["notify"]),
+        ];
+
+        post_validate[0].rollback = Some(shell_rollback(&format!("echo create-db >> {}", journal)));
+        post_validate[1].rollback = Some(shell_rollback(&format!("echo create-bucket >> {}", journal)));
+
+        let dependencies = [vec![], vec![0], vec![1], vec![2]];
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+
+        let mut tasks = pending_tasks(&post_validate, &dependencies);
+        execute_tasks(&mut tasks, &post_validate, &json!({}), &[], &ctx, |_| async {}).await;
+        rollback_tasks(&mut tasks, post_validate.len(), &json!({}), &[], &ctx, |_| async {}).await;
+
+        // the tasks are undone in the reverse order
+        assert_eq!(std::fs::read_to_string(journal).unwrap(), "create-bucket\ncreate-db\n");
+        assert_eq!(tasks.len(), 6);
+        assert_eq!(tasks[4].rollback_of, Some(1));
+        assert_eq!(tasks[5].rollback_of, Some(0));
+        assert!(matches!(run_status(&tasks), Status::RolledBack));
+
+        // a failed rollback does not prevent the next ones
+        post_validate[1].rollback = Some(shell_rollback("exit 1"));
+        std::fs::remove_file(journal).unwrap();
+
+        let mut tasks = pending_tasks(&post_validate, &dependencies);
+        execute_tasks(&mut tasks, &post_validate, &json!({}), &[], &ctx, |_| async {}).await;
+        rollback_tasks(&mut tasks, post_validate.len(), &json!({}), &[], &ctx, |_| async {}).await;
+
+        assert_eq!(std::fs::read_to_string(journal).unwrap(), "create-db\n");
+        assert_eq!(tasks[4].status, TaskStatus::Failure);
+        assert!(matches!(run_status(&tasks), Status::RollbackFailed));
+    }
+
+    #[tokio::test]
+    async fn test_execute_tasks_with_run_timeout() {
+        let mut post_validate = vec![
+            shell_task("create-bucket", "exit 0", &[]),
+            shell_task("create-db", "sleep 30", &["create-bucket"]),
+            shell_task("seed-db", "exit 0", &["create-db"]),
+        ];
+
+        let mut tasks = pending_tasks(&post_validate, &[vec![], vec![0], vec![1]]);
+        let ctx = ExecutionContext::new("section-1", "action-1", None)
+            .with_deadline(Some(Instant::now() + Duration::from_secs(1)));
+
+        execute_tasks(&mut tasks, &post_validate, &json!({}), &[], &ctx, |_| async {}).await;
+
+        assert_eq!(tasks[1].status, TaskStatus::TimedOut);
+        assert!(tasks[1].message.as_ref().unwrap().contains("timed out"));
+        assert_eq!(tasks[2].status, TaskStatus::Skipped);
+        assert!(matches!(run_status(&tasks), Status::TimedOut));
+        assert!(rollback_status(&tasks).is_none());
+
+        // the run is still reported as timed out once the previous tasks are rolled back
+        post_validate[0].rollback = Some(shell_rollback("exit 0"));
+
+        let mut tasks = pending_tasks(&post_validate, &[vec![], vec![0], vec![1]]);
+        let ctx = ctx.with_deadline(Some(Instant::now() + Duration::from_secs(1)));
+
+        execute_tasks(&mut tasks, &post_validate, &json!({}), &[], &ctx, |_| async {}).await;
+        rollback_tasks(&mut tasks, post_validate.len(), &json!({}), &[], &ctx, |_| async {}).await;
+
+        assert_eq!(tasks[3].status, TaskStatus::Success);
+        assert!(matches!(run_status(&tasks), Status::TimedOut));
+        assert!(matches!(rollback_status(&tasks), Some(Status::RolledBack)));
+    }
+
+    #[test]
+    fn test_run_deadline() {
+        let post_validate = vec![shell_task("create-bucket", "exit 0", &[]), shell_task("create-db", "exit 0", &["create-bucket"])];
+        let mut tasks = pending_tasks(&post_validate, &[vec![], vec![0]]);
+
+        // a new run gets its whole timeout
+        let deadline = run_deadline(600, &tasks);
+        assert!(deadline > Instant::now() + Duration::from_secs(590));
+
+        // a resumed run only gets what is left since its first task started
+        tasks[0].started_at = Some(chrono::Utc::now() - chrono::Duration::seconds(500));
+        tasks[1].started_at = Some(chrono::Utc::now() - chrono::Duration::seconds(100));
+        let deadline = run_deadline(600, &tasks);
+        assert!(deadline < Instant::now() + Duration::from_secs(101));
+        assert!(deadline > Instant::now() + Duration::from_secs(90));
+
+        tasks[0].started_at = Some(chrono::Utc::now() - chrono::Duration::seconds(900));
+        assert!(run_deadline(600, &tasks) <= Instant::now());
+    }
+
+    #[tokio::test]
+    async fn test_execute_tasks_in_dry_run() {
+        let mut plan = shell_task("plan", r#"test "$TORII_DRY_RUN" = 1"#, &[]);
+        plan.supports_dry_run = Some(true);
+
+        let mut notify = shell_task("notify", "exit 0", &[]);
+        notify.supports_dry_run = Some(false);
+
+        let post_validate = vec![plan, shell_task("delete", "exit 1", &[]), notify];
+
+        let mut tasks = pending_tasks(&post_validate, &[vec![], vec![], vec![]]);
+        let ctx = ExecutionContext::new("section-1", "action-1", None).with_dry_run(true);
+
+        execute_tasks(&mut tasks, &post_validate, &json!({}), &[], &ctx, |_| async {}).await;
+
+        assert_eq!(tasks[0].status, TaskStatus::Success);
+        assert_eq!(tasks[0].exit_code, Some(0));
+        assert!(tasks[0].started_at.unwrap() <= tasks[0].finished_at.unwrap());
+        assert_eq!(tasks[1].status, TaskStatus::Skipped);
+        assert_eq!(tasks[1].started_at, None);
+        assert_eq!(tasks[2].status, TaskStatus::Skipped);
+        assert!(matches!(run_status(&tasks), Status::Success));
+    }
+
+    fn shell_validate_script(script: &str) -> ValidationScriptPayload {
+        ValidationScriptPayload::new(SelfServiceSectionActionValidateYamlConfig {
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            timeout: None,
+            env: None,
+            working_dir: None,
+            inherit_env: None,
+            input_mode: None,
+            execution_policy: None,
+            executor: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_execute_validate_scripts() {
+        let mut scripts = vec![
+            shell_validate_script("exit 0"),
+            shell_validate_script(r#"echo '{"field_errors": {"name": ["name already taken"]}}' >&2; exit 1"#),
+            shell_validate_script("exit 0"),
+        ];
+
+        let ctx = ExecutionContext::new("section-1", "action-1", None);
+        let mut changes = 0;
+
+        execute_validate_scripts(&mut scripts, &json!({ "name": "my-env" }), &[], &ctx, |_| {
+            changes += 1;
+            async {}
+        }).await;
+
+        assert_eq!(scripts[0].status, TaskStatus::Success);
+        assert_eq!(scripts[1].status, TaskStatus::Failure);
+        assert_eq!(scripts[1].errors().unwrap().field_errors["name"], vec!["name already taken".to_string()]);
+        assert!(scripts[1].errors().unwrap().message.unwrap().contains("failed"));
+        assert_eq!(scripts[2].status, TaskStatus::Skipped);
+        assert_eq!(changes, 3);
+        assert!(matches!(validation_status(&scripts), Status::Failure));
+        assert!(matches!(validation_status(&scripts[..1]), Status::Success));
+    }
+
+    #[tokio::test]
+    async fn test_check_validation_without_validation_id() {
+        // no query is sent to the database without validation id
+        let pg_pool = sqlx::postgres::PgPoolOptions::new().connect_lazy("postgres://localhost/torii").unwrap();
+
+        let mut action = SelfServiceSectionActionYamlConfig {
+            slug: "action-1".to_string(),
+            name: "Action 1".to_string(),
+            description: None,
+            icon: None,
+            icon_color: None,
+            timeout: None,
+            require_validation: None,
+            fields: None,
+            validate: None,
+            post_validate: None,
+        };
+
+        let req = ExecValidateScriptRequest {
+            payload: json!({}),
+            triggered_by: None,
+            validation_id: None,
+            dry_run: false,
+        };
+
+        assert!(check_validation(&pg_pool, "section-1", &action, &req).await.is_ok());
+
+        action.require_validation = Some(true);
+        let err = check_validation(&pg_pool, "section-1", &action, &req).await.unwrap_err();
+        assert!(err.contains("requires a successful validation"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_next_permit() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let heartbeat = Heartbeat::default();
+
+        let permit = next_permit(&semaphore, &heartbeat).await.unwrap();
+
+        // no permit left until the running validation is over
+        assert!(tokio::time::timeout(Duration::from_millis(100), next_permit(&semaphore, &heartbeat)).await.is_err());
+
+        drop(permit);
+        assert!(next_permit(&semaphore, &heartbeat).await.is_some());
     }
 }
@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+
+use crate::yaml_config::{ExecutionPolicyYamlConfig, ExecutorYamlConfig, ExternalCommand, InputMode};
+
+/// Replace the values of the secret fields in what is shown to the users
+const MASK: &str = "****";
+
+/// Values available to the `{{ ... }}` placeholders of a task:
+/// - `fields.<field slug>` - value of a field of the submitted form
+/// - `outputs.<task id>.<key>` - output of a previous task
+pub struct TemplateContext<'a> {
+    pub fields: &'a serde_json::Value,
+    pub outputs: &'a BTreeMap<String, serde_json::Value>,
+    /// slugs of the fields masked by `render_masked`
+    pub secret_fields: &'a [String],
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse(template: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = vec![];
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+
+        let end = rest[start..].find("}}")
+            .ok_or_else(|| format!("unclosed placeholder in '{}'", template))?;
+
+        let expression = rest[start + 2..start + end].trim();
+
+        if expression.is_empty() {
+            return Err(format!("empty placeholder in '{}'", template));
+        }
+
+        segments.push(Segment::Placeholder(expression));
+        rest = &rest[start + end + 2..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+
+    Ok(segments)
+}
+
+/// Return the expressions of all the placeholders of the template
+pub fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+    Ok(parse(template)?.into_iter().filter_map(|segment| match segment {
+        Segment::Placeholder(expression) => Some(expression),
+        Segment::Text(_) => None,
+    }).collect())
+}
+
+/// Check at config load that the placeholders only reference known fields and previous tasks
+pub fn validate_placeholders(template: &str, field_slugs: &[&str], task_ids: &[&str]) -> Result<(), String> {
+    for expression in placeholders(template)? {
+        validate_path(expression, field_slugs, task_ids)
+            .map_err(|err| format!("{} in placeholder '{{{{ {} }}}}'", err, expression))?;
+    }
+
+    Ok(())
+}
+
+/// Check that a `fields.<slug>` or `outputs.<task id>.<key>` path only references known fields and tasks
+pub fn validate_path(path: &str, field_slugs: &[&str], task_ids: &[&str]) -> Result<(), String> {
+    let mut keys = path.split('.');
+
+    match (keys.next(), keys.next()) {
+        (Some("fields"), Some(slug)) if field_slugs.contains(&slug) => Ok(()),
+        (Some("fields"), Some(slug)) => Err(format!("unknown field '{}'", slug)),
+        (Some("outputs"), Some(id)) if task_ids.contains(&id) => Ok(()),
+        (Some("outputs"), Some(id)) => Err(format!(
+            "unknown task id '{}', only the tasks it depends on can be referenced", id
+        )),
+        _ => Err(format!("invalid path '{}', expected 'fields.<slug>' or 'outputs.<task id>.<key>'", path)),
+    }
+}
+
+impl TemplateContext<'_> {
+    pub fn lookup(&self, expression: &str) -> Result<&serde_json::Value, String> {
+        let mut path = expression.split('.');
+
+        let mut value = match (path.next(), path.next()) {
+            (Some("fields"), Some(slug)) => self.fields.get(slug),
+            (Some("outputs"), Some(id)) => self.outputs.get(id),
+            _ => None,
+        }.ok_or_else(|| format!("placeholder '{{{{ {} }}}}' can't be resolved", expression))?;
+
+        for key in path {
+            value = match value {
+                serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|index| items.get(index)),
+                _ => value.get(key),
+            }.ok_or_else(|| format!("placeholder '{{{{ {} }}}}' can't be resolved", expression))?;
+        }
+
+        Ok(value)
+    }
+
+    /// Replace the placeholders with their values, as is
+    pub fn render(&self, template: &str) -> Result<String, String> {
+        self.render_with(template, |value| value.to_string())
+    }
+
+    /// Replace the placeholders with their values, escaped with the given function
+    pub fn render_with<F>(&self, template: &str, escape: F) -> Result<String, String> where F: Fn(&str) -> String {
+        self.render_segments(template, |expression| Ok(escape(&value_to_string(self.lookup(expression)?))))
+    }
+
+    /// Same as `render_with`, but the values of the secret fields are masked
+    pub fn render_masked_with<F>(&self, template: &str, escape: F) -> Result<String, String> where F: Fn(&str) -> String {
+        self.render_segments(template, |expression| match self.is_secret(expression) {
+            true => Ok(MASK.to_string()),
+            false => Ok(escape(&value_to_string(self.lookup(expression)?))),
+        })
+    }
+
+    fn is_secret(&self, expression: &str) -> bool {
+        let mut path = expression.split('.');
+
+        match (path.next(), path.next()) {
+            (Some("fields"), Some(slug)) => self.secret_fields.iter().any(|secret_field| secret_field == slug),
+            _ => false,
+        }
+    }
+
+    fn render_segments<F>(&self, template: &str, render_placeholder: F) -> Result<String, String>
+        where F: Fn(&str) -> Result<String, String> {
+        let mut rendered = String::with_capacity(template.len());
+
+        for segment in parse(template)? {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Placeholder(expression) => rendered.push_str(&render_placeholder(expression)?),
+            }
+        }
+
+        Ok(rendered)
+    }
+
+    /// Render every string of a JSON document. A string made of a single placeholder is replaced by the raw value to keep its type.
+    pub fn render_json(&self, value: &serde_json::Value) -> Result<serde_json::Value, String> {
+        Ok(match value {
+            serde_json::Value::String(template) => {
+                let segments = parse(template)?;
+
+                match segments.as_slice() {
+                    [Segment::Placeholder(expression)] => self.lookup(expression)?.clone(),
+                    _ => serde_json::Value::String(self.render(template)?),
+                }
+            }
+            serde_json::Value::Array(items) => serde_json::Value::Array(
+                items.iter().map(|item| self.render_json(item)).collect::<Result<Vec<_>, _>>()?
+            ),
+            serde_json::Value::Object(map) => serde_json::Value::Object(
+                map.iter().map(|(key, value)| Ok((key.clone(), self.render_json(value)?))).collect::<Result<_, String>>()?
+            ),
+            _ => value.clone(),
+        })
+    }
+}
+
+pub fn value_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(value) => value.clone(),
+        serde_json::Value::Null => String::new(),
+        _ => value.to_string(),
+    }
+}
+
+/// External command with the placeholders of its arguments rendered for a run.
+/// Each argument stays a single argument whatever the values are, no shell is involved.
+pub struct RenderedCommand<'a, T> where T: ExternalCommand {
+    external_command: &'a T,
+    command: Vec<String>,
+    masked_command: Vec<String>,
+}
+
+impl<'a, T> RenderedCommand<'a, T> where T: ExternalCommand {
+    pub fn new(external_command: &'a T, template_ctx: &TemplateContext<'_>) -> Result<Self, String> {
+        let mut command = vec![];
+        let mut masked_command = vec![];
+
+        for arg in external_command.get_command() {
+            let rendered_arg = template_ctx.render(arg)
+                .map_err(|err| format!("Script '{}' failed: {}", external_command.get_command().join(" "), err))?;
+
+            command.push(rendered_arg);
+            masked_command.push(template_ctx.render_masked_with(arg, |value| value.to_string())?);
+        }
+
+        Ok(Self { external_command, command, masked_command })
+    }
+}
+
+impl<T> ExternalCommand for RenderedCommand<'_, T> where T: ExternalCommand {
+    fn get_command(&self) -> &Vec<String> {
+        &self.command
+    }
+
+    fn one_liner_command(&self) -> String {
+        self.masked_command.join(" ")
+    }
+
+    fn get_timeout(&self) -> u64 {
+        self.external_command.get_timeout()
+    }
+
+    fn get_env(&self) -> Option<&BTreeMap<String, String>> {
+        self.external_command.get_env()
+    }
+
+    fn get_working_dir(&self) -> Option<&str> {
+        self.external_command.get_working_dir()
+    }
+
+    fn inherit_env(&self) -> bool {
+        self.external_command.inherit_env()
+    }
+
+    fn get_input_mode(&self) -> InputMode {
+        self.external_command.get_input_mode()
+    }
+
+    fn get_execution_policy(&self) -> Option<&ExecutionPolicyYamlConfig> {
+        self.external_command.get_execution_policy()
+    }
+
+    fn get_executor(&self) -> Option<&ExecutorYamlConfig> {
+        self.external_command.get_executor()
+    }
+}
+
+/// Percent-encode everything but the unreserved characters (RFC 3986)
+pub fn percent_encode(value: &str) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use crate::self_service::template::{RenderedCommand, TemplateContext, validate_placeholders};
+    use crate::yaml_config::{ExternalCommand, SelfServiceSectionActionValidateYamlConfig};
+
+    #[test]
+    fn test_render() {
+        let fields = json!({ "name": "my-env", "ttl": 24, "password": "hunter2" });
+        let outputs = BTreeMap::from([("create-db".to_string(), json!({ "url": "postgres://db", "hosts": ["a", "b"] }))]);
+        let secret_fields = vec!["password".to_string()];
+        let ctx = TemplateContext { fields: &fields, outputs: &outputs, secret_fields: &secret_fields };
+
+        assert_eq!(ctx.render("env {{ fields.name }} for {{fields.ttl}}h").unwrap(), "env my-env for 24h");
+        assert_eq!(ctx.render("{{ outputs.create-db.url }}/{{ outputs.create-db.hosts.1 }}").unwrap(), "postgres://db/b");
+        assert_eq!(ctx.render_with("/envs/{{ fields.name }}", |value| value.replace('-', "%2D")).unwrap(), "/envs/my%2Denv");
+        assert_eq!(ctx.render_masked_with("{{ fields.name }}:{{ fields.password }}", |value| value.to_string()).unwrap(), "my-env:****");
+        assert!(ctx.render("{{ fields.unknown }}").is_err());
+        assert!(ctx.render("{{ fields.name ").is_err());
+
+        assert_eq!(
+            ctx.render_json(&json!({ "ttl": "{{ fields.ttl }}", "label": "env-{{ fields.name }}" })).unwrap(),
+            json!({ "ttl": 24, "label": "env-my-env" })
+        );
+    }
+
+    #[test]
+    fn test_validate_placeholders() {
+        assert!(validate_placeholders("{{ fields.name }} {{ outputs.create-db.url }}", &["name"], &["create-db"]).is_ok());
+        assert!(validate_placeholders("{{ fields.unknown }}", &["name"], &[]).is_err());
+        assert!(validate_placeholders("{{ outputs.next-task.url }}", &["name"], &["create-db"]).is_err());
+        assert!(validate_placeholders("{{ name }}", &["name"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_rendered_command() {
+        let cmd = SelfServiceSectionActionValidateYamlConfig {
+            command: vec!["psql".to_string(), "--dbname={{ fields.name }}".to_string(), "{{ fields.password }}".to_string()],
+            timeout: None,
+            env: None,
+            working_dir: None,
+            inherit_env: None,
+            input_mode: None,
+            execution_policy: None,
+            executor: None,
+        };
+
+        let fields = json!({ "name": "my env; rm -rf /", "password": "hunter2" });
+        let outputs = BTreeMap::new();
+        let secret_fields = vec!["password".to_string()];
+        let ctx = TemplateContext { fields: &fields, outputs: &outputs, secret_fields: &secret_fields };
+
+        let rendered = RenderedCommand::new(&cmd, &ctx).unwrap();
+        assert_eq!(rendered.get_command(), &vec!["psql".to_string(), "--dbname=my env; rm -rf /".to_string(), "hunter2".to_string()]);
+        assert_eq!(rendered.one_liner_command(), "psql --dbname=my env; rm -rf / ****");
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::env;
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::{field, info_span, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::constants::PROGRAM_NAME;
+
+/// Initialize the logs, and the export of the spans when an OTLP endpoint is configured with the standard
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` environment variables.
+/// The returned provider must be shut down before exiting, to flush the remaining spans.
+pub fn init_tracing() -> Result<Option<SdkTracerProvider>, String> {
+    let otlp_enabled = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"].iter()
+        .any(|name| env::var(name).is_ok_and(|value| !value.is_empty()));
+
+    let provider = match otlp_enabled {
+        true => Some(tracer_provider(SpanExporter::builder().with_http().build().map_err(|err| err.to_string())?)),
+        false => None,
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(
+            env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
+        ))
+        .with(tracing_subscriber::fmt::layer())
+        .with(provider.as_ref().map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("torii"))))
+        .init();
+
+    Ok(provider)
+}
+
+fn tracer_provider(exporter: SpanExporter) -> SdkTracerProvider {
+    let service_name = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| PROGRAM_NAME.to_lowercase());
+
+    SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build()
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+struct MapInjector<'a>(&'a mut HashMap<String, String>);
+
+impl Injector for MapInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_string(), value);
+    }
+}
+
+/// W3C trace context of the current span (`traceparent` and `tracestate`), empty when the spans are not exported
+pub fn current_trace_context() -> HashMap<String, String> {
+    let mut context = HashMap::new();
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut MapInjector(&mut context))
+    });
+
+    context.retain(|_, value| !value.is_empty());
+    context
+}
+
+/// Trace context passed to the scripts, so they can continue the trace of the run
+pub fn trace_env_vars() -> Vec<(&'static str, String)> {
+    let mut context = current_trace_context();
+
+    [("traceparent", "TRACEPARENT"), ("tracestate", "TRACESTATE")].into_iter()
+        .filter_map(|(key, name)| context.remove(key).map(|value| (name, value)))
+        .collect()
+}
+
+/// Middleware opening a span per request, child of the trace context sent by the caller if any
+pub async fn trace_http_requests(matched_path: Option<MatchedPath>, req: Request, next: Next) -> Response {
+    let route = matched_path.map(|path| path.as_str().to_string()).unwrap_or_else(|| "unknown".to_string());
+
+    let span = info_span!(
+        "http_request",
+        otel.name = %format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        http.request.method = %req.method(),
+        http.route = %route,
+        http.response.status_code = field::Empty,
+    );
+
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(req.headers())));
+    let _ = span.set_parent(parent);
+
+    let response = next.run(req).instrument(span.clone()).await;
+
+    span.record("http.response.status_code", response.status().as_u16());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{Extension, Router};
+    use axum::body::Bytes;
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+    use opentelemetry::global;
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use tracing::info_span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::telemetry::{HeaderExtractor, trace_env_vars, tracer_provider};
+
+    type Received = Arc<Mutex<Vec<String>>>;
+
+    async fn collect(Extension(received): Extension<Received>, body: Bytes) {
+        received.lock().unwrap().push(String::from_utf8_lossy(&body).to_string());
+    }
+
+    /// OTLP/HTTP collector stub keeping the JSON bodies it receives
+    async fn start_collector() -> (String, Received) {
+        let received = Received::default();
+        let app = Router::new().route("/v1/traces", post(collect)).layer(Extension(received.clone()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (endpoint, received)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_trace_propagation_and_export() {
+        let (endpoint, received) = start_collector().await;
+
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpJson)
+            .with_endpoint(endpoint)
+            .build()
+            .unwrap();
+
+        let provider = tracer_provider(exporter);
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("torii")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        // trace started by the caller of the execute endpoint
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", format!("00-{}-00f067aa0ba902b7-01", trace_id).parse().unwrap());
+
+        let request_span = info_span!("http_request");
+        let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(&headers)));
+        let _ = request_span.set_parent(parent);
+        let run_context = request_span.in_scope(|| tracing::Span::current().context());
+        drop(request_span);
+
+        // the run is executed later by the worker, the process gets the trace context in its environment
+        let run_span = info_span!("run");
+        let _ = run_span.set_parent(run_context);
+
+        let env_vars = run_span.in_scope(|| info_span!("process").in_scope(trace_env_vars));
+        drop(run_span);
+
+        assert_eq!(env_vars.len(), 1);
+        assert_eq!(env_vars[0].0, "TRACEPARENT");
+        assert!(env_vars[0].1.starts_with(&format!("00-{}-", trace_id)), "{}", env_vars[0].1);
+
+        tokio::task::spawn_blocking(move || provider.shutdown()).await.unwrap().unwrap();
+
+        let received = received.lock().unwrap().join("\n");
+
+        for name in ["http_request", "run", "process"] {
+            assert!(received.contains(&format!(r#""{}""#, name)), "span '{}' not exported: {}", name, received);
+        }
+
+        assert!(received.contains(trace_id), "{}", received);
+    }
+
+    #[test]
+    fn test_trace_env_vars_without_span() {
+        assert!(trace_env_vars().is_empty());
+    }
+}
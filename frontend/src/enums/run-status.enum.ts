@@ -3,5 +3,8 @@ export enum RunStatus {
   RUNNING = "RUNNING",
   SUCCESS = "SUCCESS",
   FAILURE = "FAILURE",
+  ROLLED_BACK = "ROLLED_BACK",
+  ROLLBACK_FAILED = "ROLLBACK_FAILED",
+  TIMED_OUT = "TIMED_OUT",
   UNKNOWN = "UNKNOWN",
 }
@@ -63,7 +63,12 @@ const List = () => {
       <ul role="list" className="space-y-6">
         {catalogs.map((catalog) => (
           <li key={catalog.slug}>
-            <h2 className="mb-4 text-xl font-bold">{catalog.name}</h2>
+            <h2
+              className="mb-4 text-xl font-bold"
+              title={catalog.source_file ?? undefined}
+            >
+              {catalog.name}
+            </h2>
             <ul
               role="list"
               className="grid grid-cols-1 gap-x-6 gap-y-8 lg:grid-cols-3 xl:gap-x-8"
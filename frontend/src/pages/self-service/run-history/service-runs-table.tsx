@@ -20,7 +20,11 @@ function getStatusStyle(status: RunStatus): string {
     case RunStatus.SUCCESS:
       return "text-green-400 bg-green-400/10";
     case RunStatus.FAILURE:
+    case RunStatus.ROLLBACK_FAILED:
+    case RunStatus.TIMED_OUT:
       return "text-rose-400 bg-rose-400/10";
+    case RunStatus.ROLLED_BACK:
+      return "text-orange-400 bg-orange-400/10";
     default:
       return "text-gray-400 bg-gray-400/10";
   }
@@ -67,6 +71,7 @@ interface ServiceRunRow {
   createdAt: string;
   serviceSlug: string;
   status: string;
+  dryRun: boolean;
   tasks: number;
   executionTime: string;
 }
@@ -84,6 +89,7 @@ export default function ServiceRunsTable({
         createdAt: dayjs(row.created_at).fromNow(),
         serviceSlug: row.input_payload.name,
         status: row.status,
+        dryRun: row.dry_run,
         tasks: totalSuccessTasks(row.tasks) / row.tasks.length,
         executionTime: millisToHumanTime(getTotalExecutionTime(row.tasks)),
       })),
@@ -124,6 +130,7 @@ export default function ServiceRunsTable({
               </div>
               <div className="hidden text-sm text-gray-500 sm:block">
                 {cellProps.row.original.status}
+                {cellProps.row.original.dryRun && " (DRY_RUN)"}
               </div>
             </div>
           );
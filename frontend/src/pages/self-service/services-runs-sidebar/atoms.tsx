@@ -6,7 +6,7 @@ import { atomWithSwr } from "@/lib/atom-with-swr";
 
 export const [runsAtom, runsStatusAtom] = makeQueryAtoms<string, any[]>(
   `catalogs-runs`,
-  () => `${API_URL}/${SELF_SERVICE}/runs`,
+  () => `${API_URL}/${SELF_SERVICE}/runs?include=input_payload,tasks`,
   (params) => () => {
     return fetch(params as string)
       .then((res) => res.json())
@@ -24,7 +24,7 @@ export const [catalogRunsAtom, catalogRunsStatusAtom] = makeQueryAtoms<
   (get) => {
     const selectedCatalogSlug = get(selectedCatalogSlugAtom);
 
-    return `${API_URL}/${SELF_SERVICE}/${selectedCatalogSlug}/runs`;
+    return `${API_URL}/${SELF_SERVICE}/${selectedCatalogSlug}/runs?include=input_payload,tasks`;
   },
   (params) => () => {
     return fetch(params as string)
@@ -23,7 +23,11 @@ const getStatusColor = (status: RunStatus, asClass?: boolean) => {
     case RunStatus.RUNNING:
       return asClass ? "bg-cyan-400" : ThemeColors.PRIMARY;
     case RunStatus.FAILURE:
+    case RunStatus.ROLLBACK_FAILED:
+    case RunStatus.TIMED_OUT:
       return asClass ? "bg-rose-400" : ThemeColors.DANGER;
+    case RunStatus.ROLLED_BACK:
+      return asClass ? "bg-orange-500" : ThemeColors.WARNING;
     case RunStatus.SUCCESS:
       return asClass ? "bg-green-400" : ThemeColors.SUCCESS;
     default:
@@ -5,6 +5,7 @@ export interface Catalog {
   name: string;
   description: string;
   actions: Service[];
+  source_file?: string | null;
 }
 
 export interface Service {
@@ -44,6 +45,7 @@ export interface ServiceRun {
   status: RunStatus;
   input_payload: InputPayload;
   tasks: Task[];
+  dry_run: boolean;
 }
 
 export interface InputPayload {
@@ -55,6 +57,9 @@ export interface Task {
   post_validate_input: PostValidateInput;
   post_validate_output: PostValidateOutput;
   status: RunStatus;
+  started_at?: string | null;
+  finished_at?: string | null;
+  exit_code?: number | null;
 }
 
 export interface PostValidateInput {